@@ -0,0 +1,357 @@
+//! Generates `KEYMAP` from a keymap file, if one is picked.
+//!
+//! The keymap is either the file named by `WADDLE_KEYMAP`, relative to this crate, or
+//! `keymaps/<name>.toml` (or `.json`) for a `keymap-<name>` feature. Without either the matrix
+//! written in `src/layout.rs` is used.
+//!
+//! A keymap file is a list of layers, each a list of rows, each row a string of cells written the
+//! same way as in `layout!`:
+//!
+//! ```toml
+//! [[layers]]
+//! name = "base"
+//! rows = [
+//!     "HT(ESC, L_CTRL, 200ms) Q W E R T Y U I O P FN(toggle_nkro)",
+//!     ...
+//! ]
+//! ```
+//!
+//! or, as JSON, `{ "layers": [ { "name": "base", "rows": [ "..." ] } ] }`. The `name` is optional
+//! and only used in error messages. `FN` takes the name of a function in `src/functions.rs`. An
+//! optional top level `tri_layer = [1, 2, 3]` turns on layer 3 while 1 and 2 are both active. The
+//! optional top level `flavor` is one of `tap-preferred` (the default), `hold-on-other-key-press`
+//! or `permissive-hold` and is used by the `HT` cells that don't name their own `layout::Flavor`.
+//! `TD(COLON LS(COLON), ESC, 200ms)` is a tap dance, see `layout!` for how it is written.
+//!
+//! Combos, see `combo`, are a list of the `[row, col]` of their buttons and the key they send,
+//! written like a cell. `layers` limits one to some layers and `release = "all"` keeps its key
+//! held until all of its buttons are released instead of the first. The optional top level
+//! `combo_term` is how long after the first button the others can be pressed:
+//!
+//! ```toml
+//! combo_term = "50ms"
+//!
+//! [[combos]]
+//! keys = [[1, 4], [1, 5]]
+//! key = "ESC"
+//! layers = [0]
+//! ```
+//!
+//! The keymap sets `LAYERS` to however many layers it has, up to 256. The number of rows and keys
+//! is checked against `ROWS` and `COLS` here so a mistake points at the place in the keymap instead
+//! of at the generated code. They come from `src/dimensions.rs`, which the crate uses as well. What
+//! the keys do is checked by `src/lint.rs` when the crate compiles, the same as for the keymap in
+//! `layout.rs`.
+//!
+//! The build fails when the keymap takes more flash than `KEYMAP_FLASH_BUDGET`, the
+//! `keymap_size` example prints how much it takes.
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use serde::Deserialize;
+
+const KEYMAP_ENV: &str = "WADDLE_KEYMAP";
+const FEATURE_PREFIX: &str = "CARGO_FEATURE_KEYMAP_";
+const MAX_LAYERS: usize = 256;
+const FLAVORS: [&str; 3] = ["TapPreferred", "HoldOnOtherKeyPress", "PermissiveHold"];
+
+include!("src/dimensions.rs");
+
+#[derive(Deserialize)]
+struct Keymap {
+    /// `[lower, upper, adjust]`, see `layout::TriLayer`.
+    tri_layer: Option<[u8; 3]>,
+    #[serde(default)]
+    flavor: Flavor,
+    layers: Vec<Layer>,
+    /// Like `50ms`, `combo::COMBO_TERM_MS` if left out.
+    combo_term: Option<String>,
+    #[serde(default)]
+    combos: Vec<Combo>,
+}
+
+/// `layout::Flavor`, named in kebab case in the keymap file.
+#[derive(Deserialize, Default, Debug)]
+#[serde(rename_all = "kebab-case")]
+enum Flavor {
+    #[default]
+    TapPreferred,
+    HoldOnOtherKeyPress,
+    PermissiveHold,
+}
+
+#[derive(Deserialize)]
+struct Combo {
+    /// `[row, col]` of each button.
+    keys: Vec<[u8; 2]>,
+    key: String,
+    layers: Option<Vec<u8>>,
+    #[serde(default)]
+    release: Release,
+}
+
+/// `combo::ComboRelease`, named in kebab case in the keymap file.
+#[derive(Deserialize, Default, Debug)]
+#[serde(rename_all = "kebab-case")]
+enum Release {
+    #[default]
+    First,
+    All,
+}
+
+#[derive(Deserialize)]
+struct Layer {
+    name: Option<String>,
+    rows: Vec<String>,
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=keymaps");
+    println!("cargo:rerun-if-changed=src/dimensions.rs");
+    println!("cargo:rerun-if-env-changed={}", KEYMAP_ENV);
+    println!("cargo:rustc-check-cfg=cfg(waddle_keymap)");
+
+    let path = match keymap_path() {
+        Ok(Some(path)) => path,
+        Ok(None) => return,
+        Err(e) => fail(&e),
+    };
+    println!("cargo:rerun-if-changed={}", path.display());
+
+    match generate(&path) {
+        Ok(code) => {
+            let out = PathBuf::from(env::var("OUT_DIR").unwrap()).join("keymap.rs");
+            fs::write(out, code).unwrap();
+            println!("cargo:rustc-cfg=waddle_keymap");
+        }
+        Err(e) => fail(&format!("{}: {}", path.display(), e)),
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(1);
+}
+
+/// The keymap file to use, from `WADDLE_KEYMAP` or a `keymap-<name>` feature.
+fn keymap_path() -> Result<Option<PathBuf>, String> {
+    let dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let features: Vec<String> = env::vars()
+        .filter_map(|(k, _)| k.strip_prefix(FEATURE_PREFIX).map(|n| n.to_lowercase().replace('_', "-")))
+        .collect();
+
+    if let Ok(file) = env::var(KEYMAP_ENV) {
+        if !features.is_empty() {
+            return Err(format!("both {} and the keymap-{} feature pick a keymap", KEYMAP_ENV, features[0]));
+        }
+        return Ok(Some(dir.join(file)));
+    }
+    match features.as_slice() {
+        [] => Ok(None),
+        [name] => ["toml", "json"]
+            .iter()
+            .map(|ext| dir.join("keymaps").join(format!("{}.{}", name, ext)))
+            .find(|p| p.exists())
+            .map(Some)
+            .ok_or(format!("the keymap-{} feature needs keymaps/{}.toml or keymaps/{}.json", name, name, name)),
+        _ => Err(format!("only one keymap feature can be enabled, got keymap-{}", features.join(" and keymap-"))),
+    }
+}
+
+fn generate(path: &Path) -> Result<String, String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let keymap: Keymap = match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => serde_json::from_str(&text).map_err(|e| e.to_string())?,
+        _ => toml::from_str(&text).map_err(|e| e.to_string())?,
+    };
+
+    // Layers are numbered with a `u8`
+    if keymap.layers.is_empty() || keymap.layers.len() > MAX_LAYERS {
+        return Err(format!("expected 1 to {} layers, found {}", MAX_LAYERS, keymap.layers.len()));
+    }
+    let mut code = format!("pub const LAYERS: usize = {};\n", keymap.layers.len());
+    code.push_str(&match keymap.tri_layer {
+        Some([lower, upper, adjust]) => format!(
+            "pub const TRI_LAYER: Option<TriLayer> = Some(TriLayer {{ lower: {}, upper: {}, adjust: {} }});\n",
+            lower, upper, adjust
+        ),
+        None => String::from("pub const TRI_LAYER: Option<TriLayer> = None;\n"),
+    });
+    code.push_str(&format!("pub const FLAVOR: Flavor = Flavor::{:?};\n", keymap.flavor));
+    code.push_str("pub const KEYMAP: [[[KeyType; COLS]; ROWS]; LAYERS] = layout![\n");
+    for (l, layer) in keymap.layers.iter().enumerate() {
+        let name = match &layer.name {
+            Some(name) => format!("layer {} ({})", l, name),
+            None => format!("layer {}", l),
+        };
+        if layer.rows.len() != ROWS {
+            return Err(format!("{}: expected {} rows, found {}", name, ROWS, layer.rows.len()));
+        }
+        let mut lines = Vec::new();
+        for (r, row) in layer.rows.iter().enumerate() {
+            let cells = split_cells(row).map_err(|e| format!("{}, row {}: {}", name, r, e))?;
+            if cells.len() != COLS {
+                return Err(format!("{}, row {}: expected {} keys, found {}", name, r, COLS, cells.len()));
+            }
+            let cells = cells
+                .iter()
+                .enumerate()
+                .map(|(c, cell)| cell_code(cell).map_err(|e| format!("{}, row {}, col {}: {}", name, r, c, e)))
+                .collect::<Result<Vec<_>, _>>()?;
+            lines.push(format!("        {}", cells.join(" ")));
+        }
+        code.push_str(&format!("    [ // {}\n{}\n    ]\n", name, lines.join(" |\n")));
+    }
+    code.push_str("];\n");
+    code.push_str(&combos_code(&keymap)?);
+    Ok(code)
+}
+
+/// `pub const COMBOS: Combos = combos![...];` for the combos in `keymap`.
+fn combos_code(keymap: &Keymap) -> Result<String, String> {
+    let mut lines = Vec::new();
+    for (i, combo) in keymap.combos.iter().enumerate() {
+        let fail = |e: String| format!("combo {}: {}", i, e);
+        if combo.keys.len() < 2 || combo.keys.len() > COMBO_KEYS {
+            return Err(fail(format!("expected 2 to {} keys, found {}", COMBO_KEYS, combo.keys.len())));
+        }
+        for (k, [row, col]) in combo.keys.iter().enumerate() {
+            if *row as usize >= ROWS || *col as usize >= COLS {
+                return Err(fail(format!("[{}, {}] is outside the {} by {} matrix", row, col, ROWS, COLS)));
+            }
+            if combo.keys[..k].contains(&[*row, *col]) {
+                return Err(fail(format!("[{}, {}] is in it twice", row, col)));
+            }
+        }
+        let key = match split_cells(&combo.key).map_err(fail)?.as_slice() {
+            [key] if key.starts_with("HT(") || key.starts_with("TD(") => return Err(fail(format!("`{}` can't be a combo key", key))),
+            [key] => cell_code(key).map_err(fail)?,
+            _ => return Err(fail(format!("`{}` should be one key", combo.key))),
+        };
+        let mut line = combo.keys.iter().map(|[row, col]| format!("({}, {})", row, col)).collect::<Vec<_>>().join(" ");
+        line.push_str(&format!(" => {}", key));
+        if let Some(layers) = &combo.layers {
+            let layers: Vec<String> = layers.iter().map(|l| l.to_string()).collect();
+            line.push_str(&format!(" layers({})", layers.join(", ")));
+        }
+        if let Release::All = combo.release {
+            line.push_str(" release(All)");
+        }
+        lines.push(format!("    {},\n", line));
+    }
+    let term = match &keymap.combo_term {
+        Some(ms) if !ms.strip_suffix("ms").is_some_and(|n| n.replace('_', "").parse::<u16>().is_ok()) => {
+            return Err(format!("combo term `{}` should be in ms, like 50ms", ms));
+        }
+        Some(ms) => format!("{};", ms),
+        None => String::new(),
+    };
+    match (term.is_empty(), lines.is_empty()) {
+        (true, true) => Ok(String::from("pub const COMBOS: Combos = combos![];\n")),
+        _ => Ok(format!("pub const COMBOS: Combos = combos![{}\n{}];\n", term, lines.concat())),
+    }
+}
+
+/// Split a row on the spaces outside of parentheses, so `HT(ESC, L_CTRL, 200ms)` is one cell.
+fn split_cells(row: &str) -> Result<Vec<String>, String> {
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut depth = 0usize;
+    for c in row.chars() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1).ok_or("unmatched `)`")?,
+            c if c.is_whitespace() && depth == 0 => {
+                if !cell.is_empty() {
+                    cells.push(std::mem::take(&mut cell));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        cell.push(c);
+    }
+    if depth != 0 {
+        return Err("unmatched `(`".to_string());
+    }
+    if !cell.is_empty() {
+        cells.push(cell);
+    }
+    Ok(cells)
+}
+
+/// The `layout!` cell for a keymap cell, with `FN(name)` turned into a path to the function.
+fn cell_code(cell: &str) -> Result<String, String> {
+    if let Some(inner) = cell.strip_prefix("HT(").and_then(|c| c.strip_suffix(')')) {
+        let parts: Vec<&str> = split_top_level(inner);
+        if parts.len() != 3 && parts.len() != 4 {
+            return Err(format!("`{}` should be HT(tap, hold, time) or HT(tap, hold, time, flavor)", cell));
+        }
+        let ms = parts[2].trim();
+        if !ms.strip_suffix("ms").is_some_and(|n| n.replace('_', "").parse::<u16>().is_ok()) {
+            return Err(format!("hold time `{}` should be in ms, like 200ms", ms));
+        }
+        let tap = cell_code(parts[0].trim())?;
+        let hold = cell_code(parts[1].trim())?;
+        return match parts.get(3).map(|f| f.trim()) {
+            Some(flavor) if !FLAVORS.contains(&flavor) => {
+                Err(format!("flavor `{}` should be one of {}", flavor, FLAVORS.join(", ")))
+            }
+            Some(flavor) => Ok(format!("HT({}, {}, {}, {})", tap, hold, ms, flavor)),
+            None => Ok(format!("HT({}, {}, {})", tap, hold, ms)),
+        };
+    }
+    if let Some(inner) = cell.strip_prefix("TD(").and_then(|c| c.strip_suffix(')')) {
+        let parts: Vec<&str> = split_top_level(inner);
+        if parts.len() != 3 {
+            return Err(format!("`{}` should be TD(taps, holds, term)", cell));
+        }
+        let mut groups = Vec::new();
+        for part in &parts[..2] {
+            let keys = split_cells(part)?;
+            if keys.is_empty() || keys.len() > TAP_DANCE_TAPS {
+                return Err(format!("`{}` should have 1 to {} keys in each of taps and holds", cell, TAP_DANCE_TAPS));
+            }
+            let keys = keys
+                .iter()
+                .map(|key| match key.starts_with("HT(") || key.starts_with("TD(") {
+                    true => Err(format!("`{}` can't be in a TD", key)),
+                    false => cell_code(key),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            groups.push(keys.join(" "));
+        }
+        let ms = parts[2].trim();
+        if !ms.strip_suffix("ms").is_some_and(|n| n.replace('_', "").parse::<u16>().is_ok()) {
+            return Err(format!("tapping term `{}` should be in ms, like 200ms", ms));
+        }
+        return Ok(format!("TD({}, {}, {})", groups[0], groups[1], ms));
+    }
+    if let Some(name) = cell.strip_prefix("FN(").and_then(|c| c.strip_suffix(')')) {
+        return Ok(format!("FN(crate::functions::{})", name.trim()));
+    }
+    Ok(cell.to_string())
+}
+
+/// Split on the commas outside of parentheses.
+fn split_top_level(s: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
@@ -0,0 +1,14 @@
+//! Prints how much flash the keymap takes on the atmega32u4, for the keymap in `layout.rs` or the
+//! keymap file picked the same way as for a build, see `build.rs`:
+//!
+//! ```text
+//! WADDLE_KEYMAP=keymaps/waddle.toml cargo run --example keymap_size
+//! ```
+use waddle_core::layout::{KEYMAP_BYTES, KEYMAP_FLASH_BUDGET, LAYERS};
+
+fn main() {
+    println!(
+        "keymap of {} layers takes {} bytes of flash on the atmega32u4, KEYMAP_FLASH_BUDGET is {}",
+        LAYERS, KEYMAP_BYTES, KEYMAP_FLASH_BUDGET
+    );
+}
@@ -0,0 +1,127 @@
+//! Combos, buttons pressed together for a key of their own.
+//!
+//! A combo fires when all of its buttons are pressed within the combo term of the first one, and
+//! nothing else is pressed in between. Until then the buttons are held back, so if it
+//! doesn't fire they are sent as they would have been, in the order they were pressed. Where a
+//! combo is part of a bigger one, the bigger one is waited for until the term is over. The buttons
+//! of a combo send nothing of their own until they are released, even after its key is.
+//!
+//! The key of a combo works as it would in the matrix, except that a `LayerTapToggle` is only
+//! momentary. `Transparent`, `PassThrough` and `Dead` need a place in the matrix and are rejected
+//! by `lint`.
+//!
+//! The combos are written with [`combos!`](crate::combos) and kept in progmem like the keymap.
+use avr_progmem::wrapper::ProgMem;
+
+use crate::layers::Layers;
+use crate::layout::Key;
+use crate::position::position::Position;
+
+pub use crate::dimensions::COMBO_KEYS;
+/// How long, in ms, after the first button of a combo the others can be pressed, unless the
+/// combos are given their own term.
+pub const COMBO_TERM_MS: u16 = 50;
+
+/// When a combo's key is released.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ComboRelease {
+    /// As soon as one of its buttons is released.
+    First,
+    /// Once all of its buttons are released.
+    All,
+}
+
+#[derive(Copy, Clone)]
+pub struct Combo {
+    /// The buttons to press, `None` after the last one.
+    pub buttons: [Option<Position>; COMBO_KEYS],
+    pub key: Key,
+    /// The layers it works on, as the highest active layer. `None` for every layer.
+    pub layers: Option<Layers>,
+    pub release: ComboRelease,
+}
+
+impl Combo {
+    /// A combo of the buttons at `(row, col)`, on every layer, released with its first button.
+    pub const fn new(buttons: &[(u8, u8)], key: Key) -> Self {
+        if buttons.len() > COMBO_KEYS {
+            panic!("a combo has at most COMBO_KEYS buttons");
+        }
+        let mut combo = Self { buttons: [None; COMBO_KEYS], key, layers: None, release: ComboRelease::First };
+        let mut i = 0;
+        while i < buttons.len() {
+            combo.buttons[i] = Some(Position::new(buttons[i].0, buttons[i].1));
+            i += 1;
+        }
+        combo
+    }
+
+    pub const fn on_layers(self, layers: &[u8]) -> Self {
+        Self { layers: Some(Layers::of(layers)), ..self }
+    }
+
+    pub const fn release_on(self, release: ComboRelease) -> Self {
+        Self { release, ..self }
+    }
+
+    /// How many buttons it has.
+    pub const fn size(&self) -> usize {
+        let mut len = 0;
+        while len < COMBO_KEYS && self.buttons[len].is_some() {
+            len += 1;
+        }
+        len
+    }
+
+    /// Whether the button at index `i` of the matrix is one of its buttons.
+    pub fn contains(&self, i: usize) -> bool {
+        self.buttons.iter().flatten().any(|p| p.index() == i)
+    }
+
+    pub fn on_layer(&self, layer: u8) -> bool {
+        match self.layers {
+            Some(layers) => layers.contains(layer),
+            None => true,
+        }
+    }
+}
+
+/// The combos of a layout, in progmem, and their term in ms. Made by [`combos!`](crate::combos).
+#[derive(Copy, Clone)]
+pub struct Combos {
+    combos: &'static [Combo],
+    term: u16,
+}
+
+impl Combos {
+    pub const NONE: Combos = Combos { combos: &[], term: COMBO_TERM_MS };
+
+    /// # Safety
+    ///
+    /// `combos` has to be in progmem on the atmega32u4, like a `progmem!` static.
+    pub const unsafe fn new(combos: &'static [Combo], term: u16) -> Self {
+        Self { combos, term }
+    }
+
+    pub const fn len(&self) -> usize {
+        self.combos.len()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.combos.is_empty()
+    }
+
+    pub const fn term(&self) -> u16 {
+        self.term
+    }
+
+    pub fn load(&self, i: usize) -> Combo {
+        // SAFETY: `new` makes sure they are in progmem
+        unsafe { ProgMem::new(&self.combos[i]) }.load()
+    }
+
+    /// A combo while compiling, where progmem is read like any other memory, for `lint`.
+    pub(crate) const fn in_const(&self, i: usize) -> &Combo {
+        &self.combos[i]
+    }
+}
@@ -0,0 +1,863 @@
+use heapless::{Deque, Vec};
+
+use crate::debounce::{DEBOUNCE_MS, Debouncer, DefaultDebouncer};
+use crate::hal::{KeyboardHal, MatrixPin};
+use crate::keycode::k;
+use crate::layout::{BUTTONS, COLS, Key, ROWS};
+use crate::mouse::MouseKeys;
+use crate::report::{CONSUMER_USAGES, ConsumerReport, KeyboardReport, NkroReport, Protocol, Report, SystemReport};
+use crate::scan::Scan;
+use crate::state::{ButtonState, State};
+use crate::state::ButtonState::Released;
+
+/// The most reports one poll pushes: the release of the old kind of keyboard report, the keyboard,
+/// consumer, system and mouse reports.
+const REPORTS_PER_POLL: usize = 5;
+
+pub enum ScanType {
+    ROW2COL,
+    COL2ROW,
+}
+
+/// The board independent part of the keyboard.
+///
+/// Scans the matrix through a [`KeyboardHal`], debounces it, feeds the result to the [`State`]
+/// and pushes a report whenever the state of the buttons changes.
+pub struct Controller {
+    scan_type: ScanType,
+    debouncer: DefaultDebouncer,
+    state: State,
+    last_button_state: [ButtonState; BUTTONS],
+    last_report: Option<Report>,
+    /// Modifiers from `Key::Modded`, only applied until another key is pressed.
+    weak_mods: u8,
+    /// When the keyboard report was last pushed, for the idle rate.
+    report_sent_at: u32,
+    last_consumer: ConsumerReport,
+    last_system: SystemReport,
+    mouse: MouseKeys,
+    /// The default layer as last loaded from or saved to the board, `None` before the first poll.
+    saved_default_layer: Option<u8>,
+    /// Reports the board had no room for, pushed again before anything newer.
+    unsent: Deque<Report, REPORTS_PER_POLL>,
+}
+
+impl Controller {
+    pub fn new(scan_type: ScanType) -> Self {
+        let state = State::new(); // Need to be broken out. If inlined atmega32u4 panics
+        Self::with_state(scan_type, state)
+    }
+
+    pub fn with_state(scan_type: ScanType, state: State) -> Self {
+        Self {
+            scan_type,
+            debouncer: DefaultDebouncer::new(DEBOUNCE_MS),
+            state,
+            last_button_state: [Released; BUTTONS],
+            last_report: None,
+            weak_mods: 0,
+            report_sent_at: 0,
+            last_consumer: ConsumerReport::empty(),
+            last_system: SystemReport::empty(),
+            mouse: MouseKeys::default(),
+            saved_default_layer: None,
+            unsent: Deque::new(),
+        }
+    }
+
+    pub fn state(&self) -> &State {
+        &self.state
+    }
+
+    pub fn poll<H: KeyboardHal>(&mut self, hal: &mut H) {
+        let now = hal.millis();
+        if self.saved_default_layer.is_none() {
+            self.state.set_default_layer(hal.load_default_layer());
+            self.saved_default_layer = Some(self.state.default_layer());
+        }
+        let raw = self.scan(hal);
+        let scan = self.debouncer.debounce(&raw, now);
+        let button_state: [ButtonState; BUTTONS] = self.state.tick(&scan, now);
+        if self.saved_default_layer != Some(self.state.default_layer()) {
+            self.saved_default_layer = Some(self.state.default_layer());
+            hal.save_default_layer(self.state.default_layer());
+        }
+        // Until the host has caught up nothing new is made, what changes in the meantime goes
+        // out in the reports after it. The unsent ones can then never be more than one poll's.
+        if !self.push_unsent(hal) {
+            self.set_leds(hal);
+            return;
+        }
+        // A host in boot protocol only understands the 8 byte keyboard report, so the extras are
+        // kept released
+        let boot = hal.protocol() == Protocol::Boot;
+
+        if button_state != self.last_button_state {
+            self.last_button_state = button_state;
+            let events = self.state.keys();
+            let report = self.create_report(&events, hal.protocol());
+            if let Some(last) = self.last_report {
+                if !last.is_same_kind(&report) {
+                    self.push_report(hal, last.released());
+                }
+            }
+            self.last_report = Some(report);
+            self.report_sent_at = now;
+            self.push_report(hal, report);
+
+            // Media and system keys have their own reports, only sent when they change
+            let consumer = match boot {
+                true => ConsumerReport::empty(),
+                false => Self::create_consumer_report(&events),
+            };
+            if consumer != self.last_consumer {
+                self.last_consumer = consumer;
+                self.push_report(hal, Report::Consumer(consumer));
+            }
+            let system = match boot {
+                true => SystemReport::empty(),
+                false => Self::create_system_report(&events),
+            };
+            if system != self.last_system {
+                self.last_system = system;
+                self.push_report(hal, Report::System(system));
+            }
+        } else if let Some(report) = self.last_report {
+            let idle = hal.idle_ms();
+            if idle != 0 && now.wrapping_sub(self.report_sent_at) >= idle as u32 {
+                self.report_sent_at = now;
+                self.push_report(hal, report);
+            }
+        }
+        // Held mouse keys keep moving the cursor, so they are checked on every poll
+        let mouse_keys = match boot {
+            true => Vec::new(),
+            false => self.state.mouse_keys(),
+        };
+        if let Some(mouse) = self.mouse.update(&mouse_keys, now) {
+            self.push_report(hal, Report::Mouse(mouse));
+        }
+        self.set_leds(hal);
+    }
+
+    /// Push a report, or keep it for the next poll if the board has no room or older ones are
+    /// still waiting.
+    fn push_report<H: KeyboardHal>(&mut self, hal: &mut H, report: Report) {
+        if !self.unsent.is_empty() || !hal.push_report(&report) {
+            // Only made after the last poll's were all pushed, so there is room
+            let _ = self.unsent.push_back(report);
+        }
+    }
+
+    /// Push the reports that are waiting, oldest first. Whether they all went.
+    fn push_unsent<H: KeyboardHal>(&mut self, hal: &mut H) -> bool {
+        while let Some(report) = self.unsent.front() {
+            if !hal.push_report(report) {
+                return false;
+            }
+            self.unsent.pop_front();
+        }
+        true
+    }
+
+    fn scan<H: KeyboardHal>(&mut self, hal: &mut H) -> Scan {
+        match self.scan_type {
+            ScanType::ROW2COL => {
+                self.scan_row2col(hal)
+            }
+            ScanType::COL2ROW => {
+                self.scan_col2row(hal)
+            }
+        }
+    }
+
+    fn scan_row2col<H: KeyboardHal>(&mut self, hal: &mut H) -> Scan {
+        let mut scan_state = Scan::new();
+        for r in 0..ROWS {
+            hal.set_low(MatrixPin::Row(r));
+            for c in 0..COLS {
+                if hal.is_low(MatrixPin::Col(c)) {
+                    scan_state.set_pressed(&r, &c);
+                }
+            }
+            hal.set_high(MatrixPin::Row(r));
+        }
+        scan_state
+    }
+
+    fn scan_col2row<H: KeyboardHal>(&mut self, hal: &mut H) -> Scan {
+        let mut scan_state = Scan::new();
+        for c in 0..COLS {
+            hal.set_low(MatrixPin::Col(c));
+            for r in 0..ROWS {
+                if hal.is_low(MatrixPin::Row(r)) {
+                    scan_state.set_pressed(&r, &c);
+                }
+            }
+            hal.set_high(MatrixPin::Col(c));
+        }
+        scan_state
+    }
+
+    fn set_leds<H: KeyboardHal>(&mut self, hal: &mut H) {
+        for (i, active) in self.state.led_state().iter().enumerate() {
+            hal.set_led(i, *active);
+        }
+    }
+
+    /// Build the report for the events. NKRO unless it is turned off or the host wants the boot
+    /// protocol, which only knows about 6 keys.
+    fn create_report(&mut self, events: &Vec<Key, BUTTONS>, protocol: Protocol) -> Report {
+        let mut mods: u8 = events.iter()
+            .filter_map(|key| match key {
+                Key::KeyCode(kc) => Some(*kc),
+                Key::Modded { code, .. } => Some(*code),
+                _ => None,
+            })
+            .filter(k::is_mod)
+            .map(k::to_mod_bitfield)
+            .fold(0, |acc, m| acc | m);
+        mods |= self.weak_mods(events);
+        // Held one-shot keys are plain modifiers, tapped ones go with the next key
+        mods |= events.iter()
+            .filter_map(|key| match key {
+                Key::OneShotMod(mods) => Some(*mods),
+                _ => None,
+            })
+            .fold(self.state.one_shot_mods(), |acc, m| acc | m);
+
+        let key_codes = events.iter()
+            .filter_map(|e| match e {
+                Key::KeyCode(kc) => Some(*kc),
+                Key::Modded { code, .. } => Some(*code),
+                _ => None,
+            })
+            .filter(|code| *code != k::NONE)
+            .filter(k::is_not_mod);
+
+        match protocol == Protocol::Report && self.state.nkro() {
+            true => {
+                let mut report = NkroReport::empty();
+                report.modifier = mods;
+                key_codes.for_each(|k| report.press(k));
+                Report::Nkro(report)
+            }
+            false => {
+                let mut report = KeyboardReport::empty();
+                report.modifier = mods;
+                for (i, k) in key_codes.enumerate() {
+                    if i > 5 { break; }
+                    report.keycodes[i] = k;
+                }
+                Report::Keyboard(report)
+            }
+        }
+    }
+
+    /// The modifiers the held `Key::Modded`s add to the report.
+    ///
+    /// A modded key's modifiers are sent along with it when it is pressed, and are dropped as
+    /// soon as another key is pressed so they do not leak onto that key. A modded key without a
+    /// usage, like `HYPER(k::NONE)`, holds its modifiers like a normal modifier key.
+    fn weak_mods(&mut self, events: &Vec<Key, BUTTONS>) -> u8 {
+        let last = self.last_report;
+        let was_pressed = |code: u8| last.is_some_and(|r| r.has_key(code));
+        let mut held = 0;
+        let mut pressed = 0;
+        let mut sticky = 0;
+        let mut other_pressed = false;
+        for event in events {
+            match *event {
+                Key::Modded { mods, code: k::NONE } => sticky |= mods,
+                Key::Modded { mods, code } => {
+                    held |= mods;
+                    if !was_pressed(code) {
+                        pressed |= mods;
+                    }
+                }
+                Key::KeyCode(kc) if k::is_not_mod(&kc) && !was_pressed(kc) => other_pressed = true,
+                _ => {}
+            }
+        }
+        if pressed != 0 {
+            self.weak_mods = pressed;
+        } else if other_pressed {
+            self.weak_mods = 0;
+        }
+        // Released modded keys take their modifiers with them
+        self.weak_mods &= held;
+        self.weak_mods | sticky
+    }
+
+    fn create_consumer_report(events: &Vec<Key, BUTTONS>) -> ConsumerReport {
+        let mut report = ConsumerReport::empty();
+        let usages = events.iter()
+            .filter_map(|e| match e {
+                Key::Consumer(usage) => Some(*usage),
+                _ => None,
+            });
+        for (i, usage) in usages.enumerate() {
+            if i >= CONSUMER_USAGES { break; }
+            report.usages[i] = usage;
+        }
+        report
+    }
+
+    /// The system control report only holds one usage, the first one pressed wins.
+    fn create_system_report(events: &Vec<Key, BUTTONS>) -> SystemReport {
+        let usage = events.iter()
+            .find_map(|e| match e {
+                Key::System(usage) => Some(*usage),
+                _ => None,
+            })
+            .unwrap_or(0);
+        SystemReport { usage }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use avr_progmem::progmem;
+
+    use crate::debounce::DEBOUNCE_MS;
+    use crate::functions;
+    use crate::hal::mock::MockHal;
+    use crate::keycode::{consumer, k, mods, system};
+    use crate::keycode::mods::{C, HYPER, RA};
+    use crate::layout::{BUTTONS, COLS, dead_matrix, Key, KeyType, Layout, ROWS, TEST_LAYERS};
+    use crate::state::State;
+    use crate::mouse::MOUSE_INTERVAL_MS;
+    use crate::report::{ConsumerReport, NkroReport, Protocol, Report};
+
+    use super::{Controller, ScanType};
+
+    // @formatter:off
+    progmem! {
+        // The keymap written in `layout.rs`, so the tests don't change with the one the crate is
+        // built with
+        static progmem KEYMAP_MATRIX: [[[KeyType; COLS]; ROWS]; TEST_LAYERS] = crate::layout![
+            [
+                TAB    Q      W         E     R     T     Y      U     I     O    P        SE(Å)  |
+                ESC    A      S         D     F     G     H      J     K     L    SE(Ö)    SE(Ä)  |
+                L_SHFT Z      X         C     V     B     N      M     COMMA DOT  SE(DASH) R_SHFT |
+                L_CTRL L_SUPR BS_N_PIPE L_ALT MO(1) SPACE RETURN MO(2) R_ALT MENU R_SUPR   R_CTRL
+            ]
+            [
+                K1  K2  K3   K4  K5  K6  K7      K8      K9      K0      OBRAKET CBRAKET   |
+                ___ ___ ___  ___ ___ ___ ARROW_L ARROW_D ARROW_U ARROW_R TILDE   EQUAL     |
+                ___ ___ ___  ___ ___ ___ ___     ___     ___     ___     ___     BACKSPACE |
+                ___ ___ GACC ___ ___ ___ ___     MO(2)   ___     ___     ___     ___
+            ]
+            [
+                F1  F2            F3            F4            F5             F6     F7       F8       F9     F10       F11       F12    |
+                ___ MS(WheelLeft) MS(WheelDown) MS(WheelUp)   MS(WheelRight) INSERT HOME     PGDWN    PGUP   END       PRNT_SCRN DASH   |
+                ___ MS(Button(1)) MS(Button(3)) MS(Button(2)) ___            ___    MS(Left) MS(Down) MS(Up) MS(Right) ___       DELETE |
+                ___ ___           ___           ___           MO(1)          ___    ___      ___      ___    ___       ___       ___
+            ]
+            [
+                FN(functions::toggle_led_0) FN(functions::toggle_led_1) FN(functions::toggle_led_2) FN(functions::toggle_nkro) ___ ___ ___       ___           ___         ___       ___             SYS(SLEEP) |
+                ___                         ___                         ___                         ___                        ___ ___ CON(PREV) CON(VOL_DOWN) CON(VOL_UP) CON(NEXT) CON(PLAY_PAUSE) CON(MUTE)  |
+                ___                         ___                         ___                         ___                        ___ ___ ___       ___           ___         ___       ___             R_SHFT     |
+                ___                         ___                         ___                         ___                        ___ ___ ___       ___           ___         ___       ___             ___
+            ]
+        ];
+    }
+    // @formatter:on
+    static KEYMAP_LAYOUT: Layout = Layout::from(&KEYMAP_MATRIX).with_tri_layer(1, 2, 3);
+
+    progmem! {
+        static progmem MODDED_MATRIX: [[[KeyType; COLS]; ROWS]; TEST_LAYERS] = {
+            let mut m = dead_matrix();
+            m[0][0][0] = KeyType::Instant(RA(k::K2));
+            m[0][0][1] = KeyType::Instant(Key::KeyCode(k::A));
+            m[0][0][2] = KeyType::Instant(HYPER(k::NONE));
+            m[0][0][3] = KeyType::Instant(C(k::Z));
+            m
+        };
+    }
+    static MODDED_LAYOUT: Layout = Layout::from(&MODDED_MATRIX);
+
+    progmem! {
+        static progmem HOLD_TAP_MATRIX: [[[KeyType; COLS]; ROWS]; TEST_LAYERS] = {
+            let mut m = dead_matrix();
+            m[0][0][0] = KeyType::OnHold(Key::KeyCode(k::A), 200, Key::KeyCode(k::L_CTRL), None);
+            m[0][0][1] = KeyType::Instant(Key::KeyCode(k::S));
+            m
+        };
+    }
+    static HOLD_TAP_LAYOUT: Layout = Layout::from(&HOLD_TAP_MATRIX);
+
+    progmem! {
+        static progmem ONE_SHOT_MATRIX: [[[KeyType; COLS]; ROWS]; TEST_LAYERS] = {
+            let mut m = dead_matrix();
+            m[0][0][0] = KeyType::Instant(Key::OneShotMod(mods::L_SHFT));
+            m[0][0][1] = KeyType::Instant(Key::KeyCode(k::A));
+            m[0][0][2] = KeyType::Instant(Key::KeyCode(k::B));
+            m
+        };
+    }
+    static ONE_SHOT_LAYOUT: Layout = Layout::from(&ONE_SHOT_MATRIX);
+
+    progmem! {
+        static progmem MEDIA_MATRIX: [[[KeyType; COLS]; ROWS]; TEST_LAYERS] = {
+            let mut m = dead_matrix();
+            m[0][0][0] = KeyType::Instant(Key::Consumer(consumer::VOL_UP));
+            m[0][0][1] = KeyType::Instant(Key::KeyCode(k::A));
+            m
+        };
+    }
+    static MEDIA_LAYOUT: Layout = Layout::from(&MEDIA_MATRIX);
+
+    fn keymap_controller(scan_type: ScanType) -> Controller {
+        Controller::with_state(scan_type, State::with_layout(&KEYMAP_LAYOUT))
+    }
+
+    fn modded_controller() -> Controller {
+        Controller::with_state(ScanType::ROW2COL, State::with_layout(&MODDED_LAYOUT))
+    }
+
+    /// Poll once every ms for `ms` ms.
+    fn run(controller: &mut Controller, hal: &mut MockHal, ms: u32) {
+        for _ in 0..ms {
+            controller.poll(hal);
+            hal.now += 1;
+        }
+    }
+
+    fn scan_with(scan_type: ScanType, pressed: &[(usize, usize)]) -> [[bool; COLS]; ROWS] {
+        let mut controller = keymap_controller(scan_type);
+        let mut hal = MockHal::new();
+        pressed.iter().for_each(|(r, c)| hal.press(*r, *c));
+        let scan = controller.scan(&mut hal);
+        let mut seen = [[false; COLS]; ROWS];
+        for i in 0..BUTTONS {
+            seen[i / COLS][i % COLS] = scan.is_pressed(&i);
+        }
+        seen
+    }
+
+    fn sorted(keys: &[u8]) -> Vec<u8> {
+        let mut keys = keys.to_vec();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn both_scan_types_find_the_same_positions() {
+        let pressed = [(0, 0), (1, 11), (2, 5), (3, 0), (3, 11)];
+        let mut expected = [[false; COLS]; ROWS];
+        pressed.iter().for_each(|(r, c)| expected[*r][*c] = true);
+
+        assert_eq!(scan_with(ScanType::ROW2COL, &pressed), expected);
+        assert_eq!(scan_with(ScanType::COL2ROW, &pressed), expected);
+    }
+
+    #[test]
+    fn col2row_sends_the_key_at_the_pressed_position() {
+        let mut controller = keymap_controller(ScanType::COL2ROW);
+        let mut hal = MockHal::new();
+        hal.press(1, 10);
+        hal.press(2, 11);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_keys(), (k::to_mod_bitfield(k::R_SHFT), vec![k::COLON]));
+    }
+
+    #[test]
+    fn nothing_pressed_sends_empty_reports() {
+        let mut controller = keymap_controller(ScanType::ROW2COL);
+        let mut hal = MockHal::new();
+        run(&mut controller, &mut hal, 50);
+        assert!(hal.reports.iter().all(|r| *r == r.released()));
+    }
+
+    #[test]
+    fn key_is_sent_once_debounced_and_released() {
+        let mut controller = keymap_controller(ScanType::ROW2COL);
+        let mut hal = MockHal::new();
+        hal.press(0, 1);
+        run(&mut controller, &mut hal, DEBOUNCE_MS as u32 + 1);
+        assert_eq!(hal.last_keys(), (0, vec![k::Q]));
+
+        hal.release(0, 1);
+        run(&mut controller, &mut hal, 10);
+        assert_eq!(hal.last_keys(), (0, vec![]));
+    }
+
+    #[test]
+    fn modifiers_go_in_the_modifier_byte() {
+        let mut controller = keymap_controller(ScanType::ROW2COL);
+        let mut hal = MockHal::new();
+        hal.press(2, 0);
+        hal.press(2, 1);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_keys(), (k::to_mod_bitfield(k::L_SHFT), vec![k::Z]));
+    }
+
+    #[test]
+    fn layer_mo_switches_layer_and_pass_through_falls_down() {
+        let mut controller = keymap_controller(ScanType::ROW2COL);
+        let mut hal = MockHal::new();
+        hal.press(3, 4);
+        hal.press(0, 0);
+        hal.press(1, 1);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_keys(), (0, sorted(&[k::K1, k::A])));
+    }
+
+    #[test]
+    fn function_keys_toggle_leds() {
+        let mut controller = keymap_controller(ScanType::ROW2COL);
+        let mut hal = MockHal::new();
+        hal.press(3, 4);
+        hal.press(3, 7);
+        hal.press(0, 1);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.leds, [false, true, false]);
+    }
+
+    #[test]
+    fn held_function_key_runs_once_per_press() {
+        let mut controller = keymap_controller(ScanType::ROW2COL);
+        let mut hal = MockHal::new();
+        hal.press(3, 4);
+        hal.press(3, 7);
+        hal.press(0, 3);
+        run(&mut controller, &mut hal, 11);
+        assert!(!controller.state().nkro());
+
+        hal.press(2, 11);
+        run(&mut controller, &mut hal, 11);
+        hal.release(2, 11);
+        run(&mut controller, &mut hal, 11);
+        assert!(!controller.state().nkro());
+
+        hal.release(0, 3);
+        run(&mut controller, &mut hal, 11);
+        hal.press(0, 3);
+        run(&mut controller, &mut hal, 11);
+        assert!(controller.state().nkro());
+    }
+
+    #[test]
+    fn reports_without_room_are_pushed_later_in_order() {
+        let mut controller = Controller::with_state(ScanType::ROW2COL, State::with_layout(&MEDIA_LAYOUT));
+        let mut hal = MockHal::new();
+        run(&mut controller, &mut hal, 20);
+        hal.press(0, 0);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_consumer(), [consumer::VOL_UP]);
+
+        // Room for the keyboard report of the release but not the consumer one
+        hal.room = Some(1);
+        hal.release(0, 0);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_consumer(), [consumer::VOL_UP]);
+        hal.press(0, 1);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_keys(), (0, vec![]));
+
+        let count = hal.reports.len();
+        hal.room = None;
+        run(&mut controller, &mut hal, 2);
+        assert_eq!(hal.reports[count], Report::Consumer(ConsumerReport::empty()));
+        assert!(hal.last_consumer().is_empty());
+        assert_eq!(hal.last_keys(), (0, vec![k::A]));
+    }
+
+    #[test]
+    fn modded_key_sends_its_modifiers_with_it() {
+        let mut controller = modded_controller();
+        let mut hal = MockHal::new();
+        hal.press(0, 0);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_keys(), (mods::R_ALT, vec![k::K2]));
+
+        hal.release(0, 0);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_keys(), (0, vec![]));
+    }
+
+    #[test]
+    fn modded_key_modifiers_do_not_leak_onto_keys_pressed_after_it() {
+        let mut controller = modded_controller();
+        let mut hal = MockHal::new();
+        hal.press(0, 3);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_keys(), (mods::L_CTRL, vec![k::Z]));
+
+        hal.press(0, 1);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_keys(), (0, sorted(&[k::Z, k::A])));
+    }
+
+    #[test]
+    fn modded_key_pressed_while_another_is_held_drops_its_modifiers_on_release() {
+        let mut controller = modded_controller();
+        let mut hal = MockHal::new();
+        hal.press(0, 1);
+        run(&mut controller, &mut hal, 11);
+        hal.press(0, 0);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_keys(), (mods::R_ALT, sorted(&[k::A, k::K2])));
+
+        hal.release(0, 0);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_keys(), (0, vec![k::A]));
+    }
+
+    #[test]
+    fn modifier_only_modded_key_holds_its_modifiers() {
+        let mut controller = modded_controller();
+        let mut hal = MockHal::new();
+        hal.press(0, 2);
+        run(&mut controller, &mut hal, 11);
+        hal.press(0, 1);
+        run(&mut controller, &mut hal, 11);
+        let hyper = mods::L_CTRL | mods::L_SHFT | mods::L_ALT | mods::L_SUPR;
+        assert_eq!(hal.last_keys(), (hyper, vec![k::A]));
+    }
+
+    #[test]
+    fn nkro_sends_every_held_key() {
+        let mut controller = keymap_controller(ScanType::ROW2COL);
+        let mut hal = MockHal::new();
+        (0..10).for_each(|c| hal.press(0, c));
+        run(&mut controller, &mut hal, 11);
+        assert!(matches!(hal.last_report(), Some(Report::Nkro(_))));
+        assert_eq!(hal.last_keys().1, sorted(&[k::TAB, k::Q, k::W, k::E, k::R, k::T, k::Y, k::U, k::I, k::O]));
+    }
+
+    #[test]
+    fn boot_protocol_falls_back_to_six_keys() {
+        let mut controller = keymap_controller(ScanType::ROW2COL);
+        let mut hal = MockHal::new();
+        hal.protocol = Protocol::Boot;
+        (0..10).for_each(|c| hal.press(0, c));
+        run(&mut controller, &mut hal, 11);
+        assert!(matches!(hal.last_report(), Some(Report::Keyboard(_))));
+        assert_eq!(hal.last_keys().1, sorted(&[k::TAB, k::Q, k::W, k::E, k::R, k::T]));
+    }
+
+    #[test]
+    fn consumer_keys_are_sent_on_their_own_report() {
+        let mut controller = keymap_controller(ScanType::ROW2COL);
+        let mut hal = MockHal::new();
+        run(&mut controller, &mut hal, 20);
+        hal.press(3, 4);
+        hal.press(3, 7);
+        hal.press(1, 8);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_consumer(), [consumer::VOL_UP]);
+        assert_eq!(hal.last_keys(), (0, vec![]));
+
+        hal.release(1, 8);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_consumer(), []);
+    }
+
+    #[test]
+    fn consumer_and_keyboard_keys_can_be_held_together() {
+        let mut controller = keymap_controller(ScanType::ROW2COL);
+        let mut hal = MockHal::new();
+        run(&mut controller, &mut hal, 20);
+        hal.press(3, 4);
+        hal.press(3, 7);
+        hal.press(1, 11);
+        hal.press(2, 0);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_consumer(), [consumer::MUTE]);
+        assert_eq!(hal.last_keys(), (k::to_mod_bitfield(k::L_SHFT), vec![]));
+        let consumer_reports = hal.reports.iter().filter(|r| matches!(r, Report::Consumer(_))).count();
+        assert_eq!(consumer_reports, 1);
+    }
+
+    #[test]
+    fn system_keys_are_sent_on_their_own_report() {
+        let mut controller = keymap_controller(ScanType::ROW2COL);
+        let mut hal = MockHal::new();
+        run(&mut controller, &mut hal, 20);
+        hal.press(3, 4);
+        hal.press(3, 7);
+        hal.press(0, 11);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_system(), system::SLEEP);
+        assert_eq!(hal.last_keys(), (0, vec![]));
+
+        hal.release(0, 11);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_system(), 0);
+        let system_reports = hal.reports.iter().filter(|r| matches!(r, Report::System(_))).count();
+        assert_eq!(system_reports, 2);
+    }
+
+    #[test]
+    fn mouse_keys_move_the_cursor_while_held() {
+        let mut controller = keymap_controller(ScanType::ROW2COL);
+        let mut hal = MockHal::new();
+        run(&mut controller, &mut hal, 20);
+        hal.press(3, 7);
+        hal.press(2, 9);
+        run(&mut controller, &mut hal, 11 + 10 * MOUSE_INTERVAL_MS as u32);
+        let moves: Vec<(i8, i8)> = hal.reports.iter()
+            .filter_map(|r| match r {
+                Report::Mouse(m) => Some((m.x, m.y)),
+                _ => None,
+            })
+            .collect();
+        assert!(moves.len() >= 10);
+        assert!(moves.iter().all(|(x, y)| *x > 0 && *y == 0));
+        assert!(moves.first().unwrap().0 <= moves.last().unwrap().0);
+        assert_eq!(hal.last_keys(), (0, vec![]));
+
+        hal.release(2, 9);
+        run(&mut controller, &mut hal, 11);
+        let count = hal.reports.len();
+        run(&mut controller, &mut hal, 100);
+        assert!(hal.reports[count..].iter().all(|r| !matches!(r, Report::Mouse(_))));
+    }
+
+    #[test]
+    fn mouse_buttons_are_pressed_and_released() {
+        let mut controller = keymap_controller(ScanType::ROW2COL);
+        let mut hal = MockHal::new();
+        run(&mut controller, &mut hal, 20);
+        hal.press(3, 7);
+        hal.press(2, 1);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_mouse_buttons(), 0b1);
+
+        hal.release(2, 1);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_mouse_buttons(), 0);
+    }
+
+    #[test]
+    fn boot_protocol_only_sends_keyboard_reports() {
+        let mut controller = keymap_controller(ScanType::ROW2COL);
+        let mut hal = MockHal::new();
+        hal.protocol = Protocol::Boot;
+        run(&mut controller, &mut hal, 20);
+        // Volume up, sleep and mouse right, all on the raised layers
+        hal.press(3, 4);
+        hal.press(3, 7);
+        hal.press(1, 8);
+        hal.press(0, 11);
+        run(&mut controller, &mut hal, 11);
+        hal.release(3, 4);
+        hal.press(2, 9);
+        run(&mut controller, &mut hal, 100);
+        assert!(hal.reports.iter().all(|r| matches!(r, Report::Keyboard(_))));
+    }
+
+    #[test]
+    fn switching_to_boot_releases_held_extras() {
+        let mut controller = keymap_controller(ScanType::ROW2COL);
+        let mut hal = MockHal::new();
+        run(&mut controller, &mut hal, 20);
+        hal.press(3, 4);
+        hal.press(3, 7);
+        hal.press(1, 8);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_consumer(), [consumer::VOL_UP]);
+
+        hal.protocol = Protocol::Boot;
+        hal.press(0, 1);
+        run(&mut controller, &mut hal, 11);
+        assert_eq!(hal.last_consumer(), []);
+    }
+
+    #[test]
+    fn keyboard_report_is_repeated_at_the_idle_rate() {
+        let mut controller = keymap_controller(ScanType::ROW2COL);
+        let mut hal = MockHal::new();
+        hal.idle_ms = 100;
+        hal.press(0, 1);
+        run(&mut controller, &mut hal, 20);
+        let count = hal.reports.len();
+        run(&mut controller, &mut hal, 300);
+        assert_eq!(hal.reports.len(), count + 3);
+        assert_eq!(hal.last_keys(), (0, vec![k::Q]));
+
+        hal.idle_ms = 0;
+        let count = hal.reports.len();
+        run(&mut controller, &mut hal, 300);
+        assert_eq!(hal.reports.len(), count);
+    }
+
+    #[test]
+    fn toggling_nkro_releases_the_old_report_kind() {
+        let mut controller = keymap_controller(ScanType::ROW2COL);
+        let mut hal = MockHal::new();
+        run(&mut controller, &mut hal, 20);
+        hal.press(3, 4);
+        hal.press(3, 7);
+        run(&mut controller, &mut hal, 11);
+        assert!(matches!(hal.last_report(), Some(Report::Nkro(_))));
+
+        hal.press(0, 3);
+        run(&mut controller, &mut hal, 11);
+        assert!(!controller.state().nkro());
+        let switch = hal.reports.iter().position(|r| matches!(r, Report::Keyboard(_))).unwrap();
+        assert_eq!(hal.reports[switch - 1], Report::Nkro(NkroReport::empty()));
+    }
+
+    #[test]
+    fn default_layer_is_restored_and_saved_through_the_hal() {
+        let mut controller = keymap_controller(ScanType::ROW2COL);
+        let mut hal = MockHal::new();
+        hal.saved_default_layer = 1;
+        run(&mut controller, &mut hal, 1);
+        assert_eq!(controller.state().default_layer(), 1);
+
+        controller.state.set_default_layer(2);
+        run(&mut controller, &mut hal, 1);
+        assert_eq!(hal.saved_default_layer, 2);
+    }
+
+    #[test]
+    fn nothing_saved_means_layer_0() {
+        let mut controller = keymap_controller(ScanType::ROW2COL);
+        let mut hal = MockHal::new();
+        run(&mut controller, &mut hal, 1);
+        assert_eq!(controller.state().default_layer(), 0);
+        assert_eq!(hal.saved_default_layer, 0xFF);
+    }
+
+    #[test]
+    fn key_pressed_during_a_hold_tap_is_sent_after_its_tap() {
+        let mut controller = Controller::with_state(ScanType::ROW2COL, State::with_layout(&HOLD_TAP_LAYOUT));
+        let mut hal = MockHal::new();
+        hal.press(0, 0);
+        run(&mut controller, &mut hal, 20);
+        hal.press(0, 1);
+        run(&mut controller, &mut hal, 20);
+        hal.release(0, 0);
+        run(&mut controller, &mut hal, 20);
+
+        let first = |usage| hal.reports.iter().position(|r| r.has_key(usage)).unwrap();
+        assert!(first(k::A) < first(k::S));
+        assert_eq!(hal.last_keys(), (0, vec![k::S]));
+    }
+
+    #[test]
+    fn tapped_one_shot_mod_goes_in_the_report_with_the_next_key() {
+        let mut controller = Controller::with_state(ScanType::ROW2COL, State::with_layout(&ONE_SHOT_LAYOUT));
+        let mut hal = MockHal::new();
+        hal.press(0, 0);
+        run(&mut controller, &mut hal, 20);
+        hal.release(0, 0);
+        run(&mut controller, &mut hal, 20);
+        assert_eq!(hal.last_keys(), (0, vec![]));
+        hal.press(0, 1);
+        run(&mut controller, &mut hal, 20);
+        assert_eq!(hal.last_keys(), (mods::L_SHFT, vec![k::A]));
+        hal.release(0, 1);
+        run(&mut controller, &mut hal, 20);
+        assert_eq!(hal.last_keys(), (0, vec![]));
+        hal.press(0, 2);
+        run(&mut controller, &mut hal, 20);
+        assert_eq!(hal.last_keys(), (0, vec![k::B]));
+    }
+}
@@ -0,0 +1,232 @@
+//! Debouncing of the raw matrix scans.
+//!
+//! A switch chatters for a few ms when it closes or opens. A [`Debouncer`] turns the raw scans
+//! into scans where every physical actuation is one press and one release.
+//! Which algorithm is used is picked at build time with the `debounce-*` features, see
+//! [`DefaultDebouncer`].
+use crate::layout::BUTTONS;
+use crate::scan::Scan;
+
+/// The debounce time, in ms, given to the [`DefaultDebouncer`].
+pub const DEBOUNCE_MS: u16 = 5;
+
+/// Symmetric defer, unless another `debounce-*` feature is enabled.
+#[cfg(not(any(feature = "debounce-eager-defer", feature = "debounce-sym-eager")))]
+pub type DefaultDebouncer = SymDefer;
+#[cfg(feature = "debounce-eager-defer")]
+pub type DefaultDebouncer = EagerPressDeferRelease;
+#[cfg(all(feature = "debounce-sym-eager", not(feature = "debounce-eager-defer")))]
+pub type DefaultDebouncer = SymEager;
+
+pub trait Debouncer {
+    /// A debouncer where a key needs `ms` ms to settle.
+    fn new(ms: u16) -> Self where Self: Sized;
+
+    /// Feed a raw scan taken at `now`, a monotonic timestamp in ms, and get the debounced scan.
+    fn debounce(&mut self, raw: &Scan, now: u32) -> Scan;
+}
+
+/// Any change is reported once the whole matrix has been still for `ms` ms.
+///
+/// Cheap, as it only keeps one timestamp, and filters out noise. Both press and release are
+/// delayed by `ms`, and a key changing resets the wait for every other key.
+pub struct SymDefer {
+    ms: u16,
+    stable: Scan,
+    last_raw: Scan,
+    changed_at: u32,
+}
+
+impl Debouncer for SymDefer {
+    fn new(ms: u16) -> Self {
+        Self {
+            ms,
+            stable: Scan::new(),
+            last_raw: Scan::new(),
+            changed_at: 0,
+        }
+    }
+
+    fn debounce(&mut self, raw: &Scan, now: u32) -> Scan {
+        if *raw != self.last_raw {
+            self.last_raw = *raw;
+            self.changed_at = now;
+        }
+        if self.stable != self.last_raw && now.wrapping_sub(self.changed_at) >= self.ms as u32 {
+            self.stable = self.last_raw;
+        }
+        self.stable
+    }
+}
+
+/// A press is reported as soon as it is seen. A release once the key has read as released for
+/// `ms` ms.
+///
+/// No added latency on press, but a single noisy read will be seen as a press.
+pub struct EagerPressDeferRelease {
+    ms: u16,
+    stable: Scan,
+    /// Truncated timestamp of when each key last read as pressed.
+    seen_pressed: [u16; BUTTONS],
+}
+
+impl Debouncer for EagerPressDeferRelease {
+    fn new(ms: u16) -> Self {
+        Self {
+            ms,
+            stable: Scan::new(),
+            seen_pressed: [0; BUTTONS],
+        }
+    }
+
+    fn debounce(&mut self, raw: &Scan, now: u32) -> Scan {
+        let now = now as u16;
+        for i in 0..BUTTONS {
+            match raw.is_pressed(&i) {
+                true => {
+                    self.seen_pressed[i] = now;
+                    self.stable.set(&i, true);
+                }
+                // Only looked at while the key is waiting to be released, which is checked on
+                // every scan, so the truncated timestamp can not wrap before it has settled.
+                false => if self.stable.is_pressed(&i) && now.wrapping_sub(self.seen_pressed[i]) >= self.ms {
+                    self.stable.set(&i, false);
+                }
+            }
+        }
+        self.stable
+    }
+}
+
+/// Every change is reported as soon as it is seen, after which the key is ignored for `ms` ms.
+///
+/// No added latency on either press or release, but a single noisy read will be seen as a tap.
+pub struct SymEager {
+    ms: u16,
+    stable: Scan,
+    locked: Scan,
+    /// Truncated timestamp of when each key last changed.
+    changed_at: [u16; BUTTONS],
+}
+
+impl Debouncer for SymEager {
+    fn new(ms: u16) -> Self {
+        Self {
+            ms,
+            stable: Scan::new(),
+            locked: Scan::new(),
+            changed_at: [0; BUTTONS],
+        }
+    }
+
+    fn debounce(&mut self, raw: &Scan, now: u32) -> Scan {
+        let now = now as u16;
+        for i in 0..BUTTONS {
+            // Locks are checked on every scan, so the truncated timestamp can not wrap before
+            // the lock is released.
+            if self.locked.is_pressed(&i) && now.wrapping_sub(self.changed_at[i]) >= self.ms {
+                self.locked.set(&i, false);
+            }
+            let pressed = raw.is_pressed(&i);
+            if !self.locked.is_pressed(&i) && pressed != self.stable.is_pressed(&i) {
+                self.stable.set(&i, pressed);
+                self.locked.set(&i, true);
+                self.changed_at[i] = now;
+            }
+        }
+        self.stable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use crate::scan::Scan;
+
+    use super::{Debouncer, EagerPressDeferRelease, SymDefer, SymEager};
+
+    const MS: u16 = 5;
+
+    /// Raw reads, one per ms, of a key being pressed and released with the contacts chattering
+    /// for a couple of ms on each transition.
+    const BOUNCY_TAP: [bool; 60] = {
+        let mut reads = [false; 60];
+        let mut t = 10;
+        while t < 40 {
+            reads[t] = true;
+            t += 1;
+        }
+        // Chatter on press
+        reads[11] = false;
+        reads[13] = false;
+        // Chatter on release
+        reads[40] = true;
+        reads[42] = true;
+        reads
+    };
+
+    /// Feeds the reads to the debouncer and returns the times of the debounced presses and
+    /// releases.
+    fn edges<D: Debouncer>(reads: &[bool]) -> (Vec<u32>, Vec<u32>) {
+        let mut debouncer = D::new(MS);
+        let mut presses = Vec::new();
+        let mut releases = Vec::new();
+        let mut was_pressed = false;
+        for (now, pressed) in reads.iter().enumerate() {
+            let mut raw = Scan::new();
+            raw.set(&7, *pressed);
+            let is_pressed = debouncer.debounce(&raw, now as u32).is_pressed(&7);
+            match (was_pressed, is_pressed) {
+                (false, true) => presses.push(now as u32),
+                (true, false) => releases.push(now as u32),
+                _ => {}
+            }
+            was_pressed = is_pressed;
+        }
+        (presses, releases)
+    }
+
+    #[test]
+    fn sym_defer_sends_one_press_and_release_after_settling() {
+        let (presses, releases) = edges::<SymDefer>(&BOUNCY_TAP);
+        assert_eq!(presses, [14 + MS as u32]);
+        assert_eq!(releases, [43 + MS as u32]);
+    }
+
+    #[test]
+    fn eager_press_defer_release_sends_one_press_at_once_and_one_release_after_settling() {
+        let (presses, releases) = edges::<EagerPressDeferRelease>(&BOUNCY_TAP);
+        assert_eq!(presses, [10]);
+        assert_eq!(releases, [42 + MS as u32]);
+    }
+
+    #[test]
+    fn sym_eager_sends_one_press_and_release_at_once() {
+        let (presses, releases) = edges::<SymEager>(&BOUNCY_TAP);
+        assert_eq!(presses, [10]);
+        assert_eq!(releases, [41]);
+    }
+
+    #[test]
+    fn sym_defer_ignores_noise() {
+        let mut reads = [false; 30];
+        reads[10] = true;
+        reads[12] = true;
+        let (presses, releases) = edges::<SymDefer>(&reads);
+        assert!(presses.is_empty());
+        assert!(releases.is_empty());
+    }
+
+    #[test]
+    fn fast_taps_are_all_seen() {
+        // Three clean 20 ms taps with 20 ms between them.
+        let mut reads = [false; 130];
+        for start in [10, 50, 90] {
+            reads[start..start + 20].iter_mut().for_each(|r| *r = true);
+        }
+        assert_eq!(edges::<SymDefer>(&reads).0.len(), 3);
+        assert_eq!(edges::<EagerPressDeferRelease>(&reads).0.len(), 3);
+        assert_eq!(edges::<SymEager>(&reads).0.len(), 3);
+    }
+}
@@ -0,0 +1,11 @@
+// Sizes that `build.rs` checks a keymap file against. It `include!`s this file, so keep it to
+// plain constants.
+
+/// Rows of the matrix.
+pub const ROWS: usize = 4;
+/// Buttons in each row of the matrix.
+pub const COLS: usize = 12;
+/// How many taps a `TapDance` tells apart.
+pub const TAP_DANCE_TAPS: usize = 3;
+/// The most buttons in one combo.
+pub const COMBO_KEYS: usize = 4;
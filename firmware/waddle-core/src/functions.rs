@@ -0,0 +1,22 @@
+//! Functions for `Key::Function`, by name.
+//!
+//! A keymap file can only refer to functions in here, written like `FN(toggle_nkro)`. The build
+//! script turns that into a path to the function, so a new function only needs to be added here
+//! and a misspelled one is a compile error naming it.
+use crate::state::State;
+
+pub fn toggle_led_0(state: &mut State) {
+    state.toggle_led(0);
+}
+
+pub fn toggle_led_1(state: &mut State) {
+    state.toggle_led(1);
+}
+
+pub fn toggle_led_2(state: &mut State) {
+    state.toggle_led(2);
+}
+
+pub fn toggle_nkro(state: &mut State) {
+    state.toggle_nkro();
+}
@@ -0,0 +1,198 @@
+use crate::report::{Protocol, Report};
+
+/// A pin in the key matrix.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum MatrixPin {
+    Row(usize),
+    Col(usize),
+}
+
+/// Everything the [`Controller`](crate::controller::Controller) needs from the board.
+///
+/// The matrix is active low, so a selected output is driven low and a pressed key reads low on
+/// the input it is connected to. Which of rows and cols are outputs depends on the
+/// [`ScanType`](crate::controller::ScanType).
+pub trait KeyboardHal {
+    fn set_low(&mut self, pin: MatrixPin);
+    fn set_high(&mut self, pin: MatrixPin);
+    fn is_low(&mut self, pin: MatrixPin) -> bool;
+
+    fn set_led(&mut self, led: usize, on: bool);
+
+    /// Milliseconds since boot from a monotonic clock. Allowed to wrap.
+    fn millis(&self) -> u32;
+
+    /// The protocol the host has selected.
+    fn protocol(&self) -> Protocol;
+
+    /// The idle rate set by the host, in ms. The keyboard report is sent again if nothing has
+    /// changed for this long. 0 means it is only sent on change.
+    fn idle_ms(&self) -> u16;
+
+    /// The default layer last given to `save_default_layer`. Anything past the last layer, like
+    /// erased EEPROM, means none has been saved.
+    fn load_default_layer(&mut self) -> u8;
+
+    /// Keep the default layer over a power cycle. Only called when it changes.
+    fn save_default_layer(&mut self, layer: u8);
+
+    /// Queue a report for the host. False when there is no room for it, the controller keeps it
+    /// and pushes it again on the next poll. Nothing that was queued may be dropped for it.
+    fn push_report(&mut self, report: &Report) -> bool;
+}
+
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::vec::Vec;
+
+    use crate::layout::{COLS, LEDS, ROWS};
+    use crate::report::{Protocol, Report};
+
+    use super::{KeyboardHal, MatrixPin};
+
+    /// A fake matrix where a closed switch connects its row and col.
+    /// An input reads low if any output connected to it through a closed switch is driven low.
+    pub struct MockHal {
+        pub closed: [[bool; COLS]; ROWS],
+        pub leds: [bool; LEDS],
+        pub reports: Vec<Report>,
+        pub protocol: Protocol,
+        pub idle_ms: u16,
+        /// What a real board would keep in EEPROM.
+        pub saved_default_layer: u8,
+        pub now: u32,
+        /// How many more reports there is room for, `None` for as many as are pushed.
+        pub room: Option<usize>,
+        low: Vec<MatrixPin>,
+    }
+
+    impl MockHal {
+        pub fn new() -> Self {
+            Self {
+                closed: [[false; COLS]; ROWS],
+                leds: [false; LEDS],
+                reports: Vec::new(),
+                protocol: Protocol::Report,
+                idle_ms: 0,
+                saved_default_layer: 0xFF,
+                now: 0,
+                room: None,
+                low: Vec::new(),
+            }
+        }
+
+        pub fn press(&mut self, row: usize, col: usize) {
+            self.closed[row][col] = true;
+        }
+
+        pub fn release(&mut self, row: usize, col: usize) {
+            self.closed[row][col] = false;
+        }
+
+        pub fn last_report(&self) -> Option<&Report> {
+            self.reports.last()
+        }
+
+        /// The modifiers and the sorted non-modifier keys of the last keyboard report.
+        pub fn last_keys(&self) -> (u8, Vec<u8>) {
+            let last = self.reports.iter().rev()
+                .find(|r| matches!(r, Report::Keyboard(_) | Report::Nkro(_)));
+            match last {
+                Some(Report::Keyboard(r)) => {
+                    let mut keys: Vec<u8> = r.keycodes.iter().copied().filter(|k| *k != 0).collect();
+                    keys.sort();
+                    (r.modifier, keys)
+                }
+                Some(Report::Nkro(r)) => (r.modifier, (0..=0xFF).filter(|u| r.is_pressed(*u)).collect()),
+                _ => (0, Vec::new()),
+            }
+        }
+
+        /// The usages held in the last consumer report.
+        pub fn last_consumer(&self) -> Vec<u16> {
+            let last = self.reports.iter().rev()
+                .find_map(|r| match r {
+                    Report::Consumer(c) => Some(c),
+                    _ => None,
+                });
+            match last {
+                Some(c) => c.usages.iter().copied().filter(|u| *u != 0).collect(),
+                None => Vec::new(),
+            }
+        }
+
+        /// The buttons held in the last mouse report.
+        pub fn last_mouse_buttons(&self) -> u8 {
+            self.reports.iter().rev()
+                .find_map(|r| match r {
+                    Report::Mouse(m) => Some(m.buttons),
+                    _ => None,
+                })
+                .unwrap_or(0)
+        }
+
+        /// The usage in the last system control report, 0 if none is held.
+        pub fn last_system(&self) -> u8 {
+            self.reports.iter().rev()
+                .find_map(|r| match r {
+                    Report::System(s) => Some(s.usage),
+                    _ => None,
+                })
+                .unwrap_or(0)
+        }
+    }
+
+    impl KeyboardHal for MockHal {
+        fn set_low(&mut self, pin: MatrixPin) {
+            if !self.low.contains(&pin) {
+                self.low.push(pin);
+            }
+        }
+
+        fn set_high(&mut self, pin: MatrixPin) {
+            self.low.retain(|p| *p != pin);
+        }
+
+        fn is_low(&mut self, pin: MatrixPin) -> bool {
+            self.low.iter().any(|driven| match (*driven, pin) {
+                (MatrixPin::Row(r), MatrixPin::Col(c)) => self.closed[r][c],
+                (MatrixPin::Col(c), MatrixPin::Row(r)) => self.closed[r][c],
+                _ => false,
+            })
+        }
+
+        fn set_led(&mut self, led: usize, on: bool) {
+            self.leds[led] = on;
+        }
+
+        fn millis(&self) -> u32 {
+            self.now
+        }
+
+        fn protocol(&self) -> Protocol {
+            self.protocol
+        }
+
+        fn idle_ms(&self) -> u16 {
+            self.idle_ms
+        }
+
+        fn load_default_layer(&mut self) -> u8 {
+            self.saved_default_layer
+        }
+
+        fn save_default_layer(&mut self, layer: u8) {
+            self.saved_default_layer = layer;
+        }
+
+        fn push_report(&mut self, report: &Report) -> bool {
+            match self.room {
+                Some(0) => return false,
+                Some(room) => self.room = Some(room - 1),
+                None => {}
+            }
+            self.reports.push(*report);
+            true
+        }
+    }
+}
@@ -0,0 +1,966 @@
+pub mod k {
+    pub const NONE: u8 = 0x00;
+    pub const ERR_ROLLOVER: u8 = 0x01;
+    pub const POST_FAIL: u8 = 0x02;
+    pub const ERR_UNDEFINED: u8 = 0x03;
+    pub const A: u8 = 0x04;
+    pub const B: u8 = 0x05;
+    pub const C: u8 = 0x06;
+    pub const D: u8 = 0x07;
+    pub const E: u8 = 0x08;
+    pub const F: u8 = 0x09;
+    pub const G: u8 = 0x0A;
+    pub const H: u8 = 0x0B;
+    pub const I: u8 = 0x0C;
+    pub const J: u8 = 0x0D;
+    pub const K: u8 = 0x0E;
+    pub const L: u8 = 0x0F;
+    pub const M: u8 = 0x10;
+    pub const N: u8 = 0x11;
+    pub const O: u8 = 0x12;
+    pub const P: u8 = 0x13;
+    pub const Q: u8 = 0x14;
+    pub const R: u8 = 0x15;
+    pub const S: u8 = 0x16;
+    pub const T: u8 = 0x17;
+    pub const U: u8 = 0x18;
+    pub const V: u8 = 0x19;
+    pub const W: u8 = 0x1A;
+    pub const X: u8 = 0x1B;
+    pub const Y: u8 = 0x1C;
+    pub const Z: u8 = 0x1D;
+
+    pub const K1: u8 = 0x1E;
+    pub const K2: u8 = 0x1F;
+    pub const K3: u8 = 0x20;
+    pub const K4: u8 = 0x21;
+    pub const K5: u8 = 0x22;
+    pub const K6: u8 = 0x23;
+    pub const K7: u8 = 0x24;
+    pub const K8: u8 = 0x25;
+    pub const K9: u8 = 0x26;
+    pub const K0: u8 = 0x27;
+
+    pub const RETURN: u8 = 0x28;
+    pub const ESC: u8 = 0x29;
+    pub const BACKSPACE: u8 = 0x2A;
+    pub const TAB: u8 = 0x2B;
+    pub const SPACE: u8 = 0x2C;
+    pub const DASH: u8 = 0x2D;
+    pub const EQUAL: u8 = 0x2E;
+    pub const OBRAKET: u8 = 0x2F;
+    pub const CBRAKET: u8 = 0x30;
+    pub const BSLASH: u8 = 0x31;
+    pub const TILDE: u8 = 0x32;
+    pub const COLON: u8 = 0x33;
+    pub const QUOTE: u8 = 0x34;
+    pub const GACC: u8 = 0x35;
+    pub const COMMA: u8 = 0x36;
+    pub const DOT: u8 = 0x37;
+    pub const SLASH: u8 = 0x38;
+    pub const CAPSLOCK: u8 = 0x39;
+
+    pub const F1: u8 = 0x3A;
+    pub const F2: u8 = 0x3B;
+    pub const F3: u8 = 0x3C;
+    pub const F4: u8 = 0x3D;
+    pub const F5: u8 = 0x3E;
+    pub const F6: u8 = 0x3F;
+    pub const F7: u8 = 0x40;
+    pub const F8: u8 = 0x41;
+    pub const F9: u8 = 0x42;
+    pub const F10: u8 = 0x43;
+    pub const F11: u8 = 0x44;
+    pub const F12: u8 = 0x45;
+
+    pub const PRNT_SCRN: u8 = 0x46;
+    pub const SCRL_LCK: u8 = 0x47;
+    pub const PAUSE: u8 = 0x48;
+    pub const INSERT: u8 = 0x49;
+    pub const HOME: u8 = 0x4A;
+    pub const PGUP: u8 = 0x4B;
+    pub const DELETE: u8 = 0x4C;
+    pub const END: u8 = 0x4D;
+    pub const PGDWN: u8 = 0x4E;
+    pub const ARROW_R: u8 = 0x4F;
+    pub const ARROW_L: u8 = 0x50;
+    pub const ARROW_D: u8 = 0x51;
+    pub const ARROW_U: u8 = 0x52;
+
+    pub const NUM_LCK: u8 = 0x53;
+    pub const N_DIV: u8 = 0x54;
+    pub const N_MUL: u8 = 0x55;
+    pub const N_SUB: u8 = 0x56;
+    pub const N_ADD: u8 = 0x57;
+    pub const N_ENTER: u8 = 0x58;
+    pub const N1: u8 = 0x59;
+    pub const N2: u8 = 0x5A;
+    pub const N3: u8 = 0x5B;
+    pub const N4: u8 = 0x5C;
+    pub const N5: u8 = 0x5D;
+    pub const N6: u8 = 0x5E;
+    pub const N7: u8 = 0x5F;
+    pub const N8: u8 = 0x60;
+    pub const N9: u8 = 0x61;
+    pub const N0: u8 = 0x62;
+    pub const NDOT: u8 = 0x63;
+
+    pub const BS_N_PIPE: u8 = 0x64;
+    pub const MENU: u8 = 0x65;
+
+    pub const POWER: u8 = 0x66;
+    pub const N_EQUAL: u8 = 0x67;
+
+    pub const F13: u8 = 0x68;
+    pub const F14: u8 = 0x69;
+    pub const F15: u8 = 0x6A;
+    pub const F16: u8 = 0x6B;
+    pub const F17: u8 = 0x6C;
+    pub const F18: u8 = 0x6D;
+    pub const F19: u8 = 0x6E;
+    pub const F20: u8 = 0x6F;
+    pub const F21: u8 = 0x70;
+    pub const F22: u8 = 0x71;
+    pub const F23: u8 = 0x72;
+    pub const F24: u8 = 0x73;
+
+    pub const EXECUTE: u8 = 0x74;
+    pub const HELP: u8 = 0x75;
+    pub const KB_MENU: u8 = 0x76;
+    pub const SELECT: u8 = 0x77;
+    pub const STOP: u8 = 0x78;
+    pub const AGAIN: u8 = 0x79;
+    pub const UNDO: u8 = 0x7A;
+    pub const CUT: u8 = 0x7B;
+    pub const COPY: u8 = 0x7C;
+    pub const PASTE: u8 = 0x7D;
+    pub const FIND: u8 = 0x7E;
+    pub const MUTE: u8 = 0x7F;
+    pub const VOL_UP: u8 = 0x80;
+    pub const VOL_DOWN: u8 = 0x81;
+
+    pub const LOCKING_CAPS: u8 = 0x82;
+    pub const LOCKING_NUM: u8 = 0x83;
+    pub const LOCKING_SCRL: u8 = 0x84;
+    pub const N_COMMA: u8 = 0x85;
+    pub const N_EQUAL_AS400: u8 = 0x86;
+
+    pub const INT1: u8 = 0x87;
+    pub const INT2: u8 = 0x88;
+    pub const INT3: u8 = 0x89;
+    pub const INT4: u8 = 0x8A;
+    pub const INT5: u8 = 0x8B;
+    pub const INT6: u8 = 0x8C;
+    pub const INT7: u8 = 0x8D;
+    pub const INT8: u8 = 0x8E;
+    pub const INT9: u8 = 0x8F;
+    pub const LANG1: u8 = 0x90;
+    pub const LANG2: u8 = 0x91;
+    pub const LANG3: u8 = 0x92;
+    pub const LANG4: u8 = 0x93;
+    pub const LANG5: u8 = 0x94;
+    pub const LANG6: u8 = 0x95;
+    pub const LANG7: u8 = 0x96;
+    pub const LANG8: u8 = 0x97;
+    pub const LANG9: u8 = 0x98;
+
+    // International keys go by their own names in the countries that use them
+    pub const RO: u8 = INT1;
+    pub const KANA: u8 = INT2;
+    pub const YEN: u8 = INT3;
+    pub const HENKAN: u8 = INT4;
+    pub const MUHENKAN: u8 = INT5;
+    pub const HANGUL: u8 = LANG1;
+    pub const HANJA: u8 = LANG2;
+
+    pub const ALT_ERASE: u8 = 0x99;
+    pub const SYSREQ: u8 = 0x9A;
+    pub const CANCEL: u8 = 0x9B;
+    pub const CLEAR: u8 = 0x9C;
+    pub const PRIOR: u8 = 0x9D;
+    pub const RETURN2: u8 = 0x9E;
+    pub const SEPARATOR: u8 = 0x9F;
+    pub const OUT: u8 = 0xA0;
+    pub const OPER: u8 = 0xA1;
+    pub const CLEAR_AGAIN: u8 = 0xA2;
+    pub const CRSEL: u8 = 0xA3;
+    pub const EXSEL: u8 = 0xA4;
+
+    pub const N_00: u8 = 0xB0;
+    pub const N_000: u8 = 0xB1;
+    pub const THOUSANDS_SEP: u8 = 0xB2;
+    pub const DECIMAL_SEP: u8 = 0xB3;
+    pub const CURRENCY: u8 = 0xB4;
+    pub const SUB_CURRENCY: u8 = 0xB5;
+    pub const N_OPAREN: u8 = 0xB6;
+    pub const N_CPAREN: u8 = 0xB7;
+    pub const N_OBRACE: u8 = 0xB8;
+    pub const N_CBRACE: u8 = 0xB9;
+    pub const N_TAB: u8 = 0xBA;
+    pub const N_BACKSPACE: u8 = 0xBB;
+    pub const N_A: u8 = 0xBC;
+    pub const N_B: u8 = 0xBD;
+    pub const N_C: u8 = 0xBE;
+    pub const N_D: u8 = 0xBF;
+    pub const N_E: u8 = 0xC0;
+    pub const N_F: u8 = 0xC1;
+    pub const N_XOR: u8 = 0xC2;
+    pub const N_CARET: u8 = 0xC3;
+    pub const N_PERCENT: u8 = 0xC4;
+    pub const N_LT: u8 = 0xC5;
+    pub const N_GT: u8 = 0xC6;
+    pub const N_AMP: u8 = 0xC7;
+    pub const N_AMP_AMP: u8 = 0xC8;
+    pub const N_PIPE: u8 = 0xC9;
+    pub const N_PIPE_PIPE: u8 = 0xCA;
+    pub const N_COLON: u8 = 0xCB;
+    pub const N_HASH: u8 = 0xCC;
+    pub const N_SPACE: u8 = 0xCD;
+    pub const N_AT: u8 = 0xCE;
+    pub const N_BANG: u8 = 0xCF;
+    pub const N_MEM_STORE: u8 = 0xD0;
+    pub const N_MEM_RECALL: u8 = 0xD1;
+    pub const N_MEM_CLEAR: u8 = 0xD2;
+    pub const N_MEM_ADD: u8 = 0xD3;
+    pub const N_MEM_SUB: u8 = 0xD4;
+    pub const N_MEM_MUL: u8 = 0xD5;
+    pub const N_MEM_DIV: u8 = 0xD6;
+    pub const N_PLUS_MINUS: u8 = 0xD7;
+    pub const N_CLEAR: u8 = 0xD8;
+    pub const N_CLEAR_ENTRY: u8 = 0xD9;
+    pub const N_BINARY: u8 = 0xDA;
+    pub const N_OCTAL: u8 = 0xDB;
+    pub const N_DECIMAL: u8 = 0xDC;
+    pub const N_HEX: u8 = 0xDD;
+
+
+    // Special
+    pub const L_CTRL: u8 = 0xE0;
+    pub const L_SHFT: u8 = 0xE1;
+    pub const L_ALT: u8 = 0xE2;
+    pub const L_SUPR: u8 = 0xE3;
+    pub const R_CTRL: u8 = 0xE4;
+    pub const R_SHFT: u8 = 0xE5;
+    pub const R_ALT: u8 = 0xE6;
+    pub const R_SUPR: u8 = 0xE7;
+
+
+    pub mod layer {
+        pub const LOWER: u8 = 0x00;
+        pub const RAISE: u8 = 0x00;
+    }
+
+    /// Symbols as typed on Nordic host layouts, as `Key`s to use directly in a layout.
+    /// Symbols on dead keys only show up once another key, or space, is typed after them.
+    pub mod norde {
+        /// Swedish host layout.
+        pub mod se {
+            use crate::keycode::k;
+            use crate::keycode::mods::{RA, S};
+            use crate::layout::Key;
+            use crate::layout::Key::KeyCode;
+
+            pub const SECTION: Key = KeyCode(k::GACC); // §
+            pub const HALF: Key = S(k::GACC); // ½
+            pub const EXCLAIM: Key = S(k::K1); // !
+            pub const DQUOTE: Key = S(k::K2); // "
+            pub const AT: Key = RA(k::K2); // @
+            pub const HASH: Key = S(k::K3); // #
+            pub const POUND: Key = RA(k::K3); // £
+            pub const CURRENCY: Key = S(k::K4); // ¤
+            pub const DOLLAR: Key = RA(k::K4); // $
+            pub const PERCENT: Key = S(k::K5); // %
+            pub const EURO: Key = RA(k::K5); // €
+            pub const AMP: Key = S(k::K6); // &
+            pub const SLASH: Key = S(k::K7); // /
+            pub const OBRACE: Key = RA(k::K7); // {
+            pub const OPAREN: Key = S(k::K8); // (
+            pub const OBRAKET: Key = RA(k::K8); // [
+            pub const CPAREN: Key = S(k::K9); // )
+            pub const CBRAKET: Key = RA(k::K9); // ]
+            pub const EQUAL: Key = S(k::K0); // =
+            pub const CBRACE: Key = RA(k::K0); // }
+            pub const PLUS: Key = KeyCode(k::DASH); // +
+            pub const QUESTION: Key = S(k::DASH); // ?
+            pub const BSLASH: Key = RA(k::DASH); // \
+            pub const ACUTE: Key = KeyCode(k::EQUAL); // ´, dead key
+            pub const GRAVE: Key = S(k::EQUAL); // `, dead key
+            pub const Å: Key = KeyCode(k::OBRAKET); // Å
+            pub const DIAERESIS: Key = KeyCode(k::CBRAKET); // ¨, dead key
+            pub const CARET: Key = S(k::CBRAKET); // ^, dead key
+            pub const TILDE: Key = RA(k::CBRAKET); // ~, dead key
+            pub const QUOTE: Key = KeyCode(k::TILDE); // '
+            pub const ASTERISK: Key = S(k::TILDE); // *
+            pub const Ö: Key = KeyCode(k::COLON); // Ö
+            pub const Ä: Key = KeyCode(k::QUOTE); // Ä
+            pub const LT: Key = KeyCode(k::BS_N_PIPE); // <
+            pub const GT: Key = S(k::BS_N_PIPE); // >
+            pub const PIPE: Key = RA(k::BS_N_PIPE); // |
+            pub const COMMA: Key = KeyCode(k::COMMA); // ,
+            pub const SEMICOLON: Key = S(k::COMMA); // ;
+            pub const DOT: Key = KeyCode(k::DOT); // .
+            pub const COLON: Key = S(k::DOT); // :
+            pub const DASH: Key = KeyCode(k::SLASH); // -
+            pub const UNDERSCORE: Key = S(k::SLASH); // _
+            pub const MICRO: Key = RA(k::M); // µ
+        }
+
+        /// Norwegian host layout.
+        pub mod no {
+            use crate::keycode::k;
+            use crate::keycode::mods::{RA, S};
+            use crate::layout::Key;
+            use crate::layout::Key::KeyCode;
+
+            pub const PIPE: Key = KeyCode(k::GACC); // |
+            pub const SECTION: Key = S(k::GACC); // §
+            pub const EXCLAIM: Key = S(k::K1); // !
+            pub const DQUOTE: Key = S(k::K2); // "
+            pub const AT: Key = RA(k::K2); // @
+            pub const HASH: Key = S(k::K3); // #
+            pub const POUND: Key = RA(k::K3); // £
+            pub const CURRENCY: Key = S(k::K4); // ¤
+            pub const DOLLAR: Key = RA(k::K4); // $
+            pub const PERCENT: Key = S(k::K5); // %
+            pub const EURO: Key = RA(k::K5); // €
+            pub const AMP: Key = S(k::K6); // &
+            pub const SLASH: Key = S(k::K7); // /
+            pub const OBRACE: Key = RA(k::K7); // {
+            pub const OPAREN: Key = S(k::K8); // (
+            pub const OBRAKET: Key = RA(k::K8); // [
+            pub const CPAREN: Key = S(k::K9); // )
+            pub const CBRAKET: Key = RA(k::K9); // ]
+            pub const EQUAL: Key = S(k::K0); // =
+            pub const CBRACE: Key = RA(k::K0); // }
+            pub const PLUS: Key = KeyCode(k::DASH); // +
+            pub const QUESTION: Key = S(k::DASH); // ?
+            pub const BSLASH: Key = KeyCode(k::EQUAL); // \
+            pub const GRAVE: Key = S(k::EQUAL); // `, dead key
+            pub const ACUTE: Key = RA(k::EQUAL); // ´, dead key
+            pub const Å: Key = KeyCode(k::OBRAKET); // Å
+            pub const DIAERESIS: Key = KeyCode(k::CBRAKET); // ¨, dead key
+            pub const CARET: Key = S(k::CBRAKET); // ^, dead key
+            pub const TILDE: Key = RA(k::CBRAKET); // ~, dead key
+            pub const QUOTE: Key = KeyCode(k::TILDE); // '
+            pub const ASTERISK: Key = S(k::TILDE); // *
+            pub const Ø: Key = KeyCode(k::COLON); // Ø
+            pub const Æ: Key = KeyCode(k::QUOTE); // Æ
+            pub const LT: Key = KeyCode(k::BS_N_PIPE); // <
+            pub const GT: Key = S(k::BS_N_PIPE); // >
+            pub const COMMA: Key = KeyCode(k::COMMA); // ,
+            pub const SEMICOLON: Key = S(k::COMMA); // ;
+            pub const DOT: Key = KeyCode(k::DOT); // .
+            pub const COLON: Key = S(k::DOT); // :
+            pub const DASH: Key = KeyCode(k::SLASH); // -
+            pub const UNDERSCORE: Key = S(k::SLASH); // _
+            pub const MICRO: Key = RA(k::M); // µ
+        }
+
+        /// Danish host layout.
+        pub mod dk {
+            use crate::keycode::k;
+            use crate::keycode::mods::{RA, S};
+            use crate::layout::Key;
+            use crate::layout::Key::KeyCode;
+
+            pub const HALF: Key = KeyCode(k::GACC); // ½
+            pub const SECTION: Key = S(k::GACC); // §
+            pub const EXCLAIM: Key = S(k::K1); // !
+            pub const DQUOTE: Key = S(k::K2); // "
+            pub const AT: Key = RA(k::K2); // @
+            pub const HASH: Key = S(k::K3); // #
+            pub const POUND: Key = RA(k::K3); // £
+            pub const CURRENCY: Key = S(k::K4); // ¤
+            pub const DOLLAR: Key = RA(k::K4); // $
+            pub const PERCENT: Key = S(k::K5); // %
+            pub const EURO: Key = RA(k::K5); // €
+            pub const AMP: Key = S(k::K6); // &
+            pub const SLASH: Key = S(k::K7); // /
+            pub const OBRACE: Key = RA(k::K7); // {
+            pub const OPAREN: Key = S(k::K8); // (
+            pub const OBRAKET: Key = RA(k::K8); // [
+            pub const CPAREN: Key = S(k::K9); // )
+            pub const CBRAKET: Key = RA(k::K9); // ]
+            pub const EQUAL: Key = S(k::K0); // =
+            pub const CBRACE: Key = RA(k::K0); // }
+            pub const PLUS: Key = KeyCode(k::DASH); // +
+            pub const QUESTION: Key = S(k::DASH); // ?
+            pub const ACUTE: Key = KeyCode(k::EQUAL); // ´, dead key
+            pub const GRAVE: Key = S(k::EQUAL); // `, dead key
+            pub const PIPE: Key = RA(k::EQUAL); // |
+            pub const Å: Key = KeyCode(k::OBRAKET); // Å
+            pub const DIAERESIS: Key = KeyCode(k::CBRAKET); // ¨, dead key
+            pub const CARET: Key = S(k::CBRAKET); // ^, dead key
+            pub const TILDE: Key = RA(k::CBRAKET); // ~, dead key
+            pub const QUOTE: Key = KeyCode(k::TILDE); // '
+            pub const ASTERISK: Key = S(k::TILDE); // *
+            pub const Æ: Key = KeyCode(k::COLON); // Æ
+            pub const Ø: Key = KeyCode(k::QUOTE); // Ø
+            pub const LT: Key = KeyCode(k::BS_N_PIPE); // <
+            pub const GT: Key = S(k::BS_N_PIPE); // >
+            pub const BSLASH: Key = RA(k::BS_N_PIPE); // \
+            pub const COMMA: Key = KeyCode(k::COMMA); // ,
+            pub const SEMICOLON: Key = S(k::COMMA); // ;
+            pub const DOT: Key = KeyCode(k::DOT); // .
+            pub const COLON: Key = S(k::DOT); // :
+            pub const DASH: Key = KeyCode(k::SLASH); // -
+            pub const UNDERSCORE: Key = S(k::SLASH); // _
+            pub const MICRO: Key = RA(k::M); // µ
+        }
+
+        /// Finnish host layout, the same as the Swedish one.
+        pub mod fi {
+            pub use super::se::*;
+        }
+    }
+
+    /// Symbols as typed on a German host layout, as `Key`s to use directly in a layout.
+    /// Symbols on dead keys only show up once another key, or space, is typed after them.
+    pub mod de {
+        use crate::keycode::k;
+        use crate::keycode::mods::{RA, S};
+        use crate::layout::Key;
+        use crate::layout::Key::KeyCode;
+
+        pub const Z: Key = KeyCode(k::Y); // Z, swapped with Y
+        pub const Y: Key = KeyCode(k::Z); // Y, swapped with Z
+        pub const CARET: Key = KeyCode(k::GACC); // ^, dead key
+        pub const DEGREE: Key = S(k::GACC); // °
+        pub const EXCLAIM: Key = S(k::K1); // !
+        pub const DQUOTE: Key = S(k::K2); // "
+        pub const SQUARED: Key = RA(k::K2); // ²
+        pub const SECTION: Key = S(k::K3); // §
+        pub const CUBED: Key = RA(k::K3); // ³
+        pub const DOLLAR: Key = S(k::K4); // $
+        pub const PERCENT: Key = S(k::K5); // %
+        pub const AMP: Key = S(k::K6); // &
+        pub const SLASH: Key = S(k::K7); // /
+        pub const OBRACE: Key = RA(k::K7); // {
+        pub const OPAREN: Key = S(k::K8); // (
+        pub const OBRAKET: Key = RA(k::K8); // [
+        pub const CPAREN: Key = S(k::K9); // )
+        pub const CBRAKET: Key = RA(k::K9); // ]
+        pub const EQUAL: Key = S(k::K0); // =
+        pub const CBRACE: Key = RA(k::K0); // }
+        pub const SHARP_S: Key = KeyCode(k::DASH); // ß
+        pub const QUESTION: Key = S(k::DASH); // ?
+        pub const BSLASH: Key = RA(k::DASH); // \
+        pub const ACUTE: Key = KeyCode(k::EQUAL); // ´, dead key
+        pub const GRAVE: Key = S(k::EQUAL); // `, dead key
+        pub const AT: Key = RA(k::Q); // @
+        pub const EURO: Key = RA(k::E); // €
+        pub const Ü: Key = KeyCode(k::OBRAKET); // Ü
+        pub const PLUS: Key = KeyCode(k::CBRAKET); // +
+        pub const ASTERISK: Key = S(k::CBRAKET); // *
+        pub const TILDE: Key = RA(k::CBRAKET); // ~
+        pub const HASH: Key = KeyCode(k::TILDE); // #
+        pub const QUOTE: Key = S(k::TILDE); // '
+        pub const Ö: Key = KeyCode(k::COLON); // Ö
+        pub const Ä: Key = KeyCode(k::QUOTE); // Ä
+        pub const LT: Key = KeyCode(k::BS_N_PIPE); // <
+        pub const GT: Key = S(k::BS_N_PIPE); // >
+        pub const PIPE: Key = RA(k::BS_N_PIPE); // |
+        pub const COMMA: Key = KeyCode(k::COMMA); // ,
+        pub const SEMICOLON: Key = S(k::COMMA); // ;
+        pub const DOT: Key = KeyCode(k::DOT); // .
+        pub const COLON: Key = S(k::DOT); // :
+        pub const DASH: Key = KeyCode(k::SLASH); // -
+        pub const UNDERSCORE: Key = S(k::SLASH); // _
+        pub const MICRO: Key = RA(k::M); // µ
+    }
+
+    /// Symbols as typed on a US International host layout, as `Key`s to use directly in a layout.
+    /// Symbols on dead keys only show up once another key, or space, is typed after them.
+    /// The accented letters are the lower case ones, add shift for upper case.
+    pub mod us_intl {
+        use crate::keycode::k;
+        use crate::keycode::mods::{RA, S};
+        use crate::layout::Key;
+        use crate::layout::Key::KeyCode;
+
+        pub const GRAVE: Key = KeyCode(k::GACC); // `, dead key
+        pub const TILDE: Key = S(k::GACC); // ~, dead key
+        pub const EXCLAIM: Key = S(k::K1); // !
+        pub const AT: Key = S(k::K2); // @
+        pub const HASH: Key = S(k::K3); // #
+        pub const DOLLAR: Key = S(k::K4); // $
+        pub const PERCENT: Key = S(k::K5); // %
+        pub const CARET: Key = S(k::K6); // ^, dead key
+        pub const AMP: Key = S(k::K7); // &
+        pub const ASTERISK: Key = S(k::K8); // *
+        pub const OPAREN: Key = S(k::K9); // (
+        pub const CPAREN: Key = S(k::K0); // )
+        pub const DASH: Key = KeyCode(k::DASH); // -
+        pub const UNDERSCORE: Key = S(k::DASH); // _
+        pub const EQUAL: Key = KeyCode(k::EQUAL); // =
+        pub const PLUS: Key = S(k::EQUAL); // +
+        pub const OBRAKET: Key = KeyCode(k::OBRAKET); // [
+        pub const OBRACE: Key = S(k::OBRAKET); // {
+        pub const CBRAKET: Key = KeyCode(k::CBRAKET); // ]
+        pub const CBRACE: Key = S(k::CBRAKET); // }
+        pub const BSLASH: Key = KeyCode(k::BSLASH); // \
+        pub const PIPE: Key = S(k::BSLASH); // |
+        pub const SEMICOLON: Key = KeyCode(k::COLON); // ;
+        pub const COLON: Key = S(k::COLON); // :
+        pub const QUOTE: Key = KeyCode(k::QUOTE); // ', dead key
+        pub const DQUOTE: Key = S(k::QUOTE); // ", dead key
+        pub const COMMA: Key = KeyCode(k::COMMA); // ,
+        pub const LT: Key = S(k::COMMA); // <
+        pub const DOT: Key = KeyCode(k::DOT); // .
+        pub const GT: Key = S(k::DOT); // >
+        pub const SLASH: Key = KeyCode(k::SLASH); // /
+        pub const QUESTION: Key = S(k::SLASH); // ?
+        pub const INV_EXCLAIM: Key = RA(k::K1); // ¡
+        pub const SQUARED: Key = RA(k::K2); // ²
+        pub const CUBED: Key = RA(k::K3); // ³
+        pub const CURRENCY: Key = RA(k::K4); // ¤
+        pub const EURO: Key = RA(k::K5); // €
+        pub const QUARTER: Key = RA(k::K6); // ¼
+        pub const HALF: Key = RA(k::K7); // ½
+        pub const THREE_QUARTERS: Key = RA(k::K8); // ¾
+        pub const YEN: Key = RA(k::DASH); // ¥
+        pub const TIMES: Key = RA(k::EQUAL); // ×
+        pub const A_UML: Key = RA(k::Q); // ä
+        pub const A_RING: Key = RA(k::W); // å
+        pub const E_ACUTE: Key = RA(k::E); // é
+        pub const REGISTERED: Key = RA(k::R); // ®
+        pub const THORN: Key = RA(k::T); // þ
+        pub const U_UML: Key = RA(k::Y); // ü
+        pub const U_ACUTE: Key = RA(k::U); // ú
+        pub const I_ACUTE: Key = RA(k::I); // í
+        pub const O_ACUTE: Key = RA(k::O); // ó
+        pub const O_UML: Key = RA(k::P); // ö
+        pub const OGUILLEMET: Key = RA(k::OBRAKET); // «
+        pub const CGUILLEMET: Key = RA(k::CBRAKET); // »
+        pub const NOT: Key = RA(k::BSLASH); // ¬
+        pub const A_ACUTE: Key = RA(k::A); // á
+        pub const SHARP_S: Key = RA(k::S); // ß
+        pub const ETH: Key = RA(k::D); // ð
+        pub const O_SLASH: Key = RA(k::L); // ø
+        pub const PILCROW: Key = RA(k::COLON); // ¶
+        pub const ACUTE: Key = RA(k::QUOTE); // ´
+        pub const ASH: Key = RA(k::Z); // æ
+        pub const COPYRIGHT: Key = RA(k::C); // ©
+        pub const N_TILDE: Key = RA(k::N); // ñ
+        pub const MICRO: Key = RA(k::M); // µ
+        pub const C_CEDILLA: Key = RA(k::COMMA); // ç
+        pub const INV_QUESTION: Key = RA(k::SLASH); // ¿
+    }
+
+    /// Every usage with its name. The first entry for a usage is its canonical name, the aliases
+    /// follow at the end.
+    ///
+    /// Only for tools and debug output on the host. On the atmega32u4 the strings would be copied
+    /// from flash into SRAM at boot, about 3 KiB of the 2.5 KiB there is, so the table and
+    /// `name`/`from_name` are left out there.
+    #[cfg(not(target_arch = "avr"))]
+    const NAMES: &[(u8, &str)] = &[
+        (NONE, "NONE"),
+        (ERR_ROLLOVER, "ERR_ROLLOVER"),
+        (POST_FAIL, "POST_FAIL"),
+        (ERR_UNDEFINED, "ERR_UNDEFINED"),
+        (A, "A"),
+        (B, "B"),
+        (C, "C"),
+        (D, "D"),
+        (E, "E"),
+        (F, "F"),
+        (G, "G"),
+        (H, "H"),
+        (I, "I"),
+        (J, "J"),
+        (K, "K"),
+        (L, "L"),
+        (M, "M"),
+        (N, "N"),
+        (O, "O"),
+        (P, "P"),
+        (Q, "Q"),
+        (R, "R"),
+        (S, "S"),
+        (T, "T"),
+        (U, "U"),
+        (V, "V"),
+        (W, "W"),
+        (X, "X"),
+        (Y, "Y"),
+        (Z, "Z"),
+        (K1, "K1"),
+        (K2, "K2"),
+        (K3, "K3"),
+        (K4, "K4"),
+        (K5, "K5"),
+        (K6, "K6"),
+        (K7, "K7"),
+        (K8, "K8"),
+        (K9, "K9"),
+        (K0, "K0"),
+        (RETURN, "RETURN"),
+        (ESC, "ESC"),
+        (BACKSPACE, "BACKSPACE"),
+        (TAB, "TAB"),
+        (SPACE, "SPACE"),
+        (DASH, "DASH"),
+        (EQUAL, "EQUAL"),
+        (OBRAKET, "OBRAKET"),
+        (CBRAKET, "CBRAKET"),
+        (BSLASH, "BSLASH"),
+        (TILDE, "TILDE"),
+        (COLON, "COLON"),
+        (QUOTE, "QUOTE"),
+        (GACC, "GACC"),
+        (COMMA, "COMMA"),
+        (DOT, "DOT"),
+        (SLASH, "SLASH"),
+        (CAPSLOCK, "CAPSLOCK"),
+        (F1, "F1"),
+        (F2, "F2"),
+        (F3, "F3"),
+        (F4, "F4"),
+        (F5, "F5"),
+        (F6, "F6"),
+        (F7, "F7"),
+        (F8, "F8"),
+        (F9, "F9"),
+        (F10, "F10"),
+        (F11, "F11"),
+        (F12, "F12"),
+        (PRNT_SCRN, "PRNT_SCRN"),
+        (SCRL_LCK, "SCRL_LCK"),
+        (PAUSE, "PAUSE"),
+        (INSERT, "INSERT"),
+        (HOME, "HOME"),
+        (PGUP, "PGUP"),
+        (DELETE, "DELETE"),
+        (END, "END"),
+        (PGDWN, "PGDWN"),
+        (ARROW_R, "ARROW_R"),
+        (ARROW_L, "ARROW_L"),
+        (ARROW_D, "ARROW_D"),
+        (ARROW_U, "ARROW_U"),
+        (NUM_LCK, "NUM_LCK"),
+        (N_DIV, "N_DIV"),
+        (N_MUL, "N_MUL"),
+        (N_SUB, "N_SUB"),
+        (N_ADD, "N_ADD"),
+        (N_ENTER, "N_ENTER"),
+        (N1, "N1"),
+        (N2, "N2"),
+        (N3, "N3"),
+        (N4, "N4"),
+        (N5, "N5"),
+        (N6, "N6"),
+        (N7, "N7"),
+        (N8, "N8"),
+        (N9, "N9"),
+        (N0, "N0"),
+        (NDOT, "NDOT"),
+        (BS_N_PIPE, "BS_N_PIPE"),
+        (MENU, "MENU"),
+        (POWER, "POWER"),
+        (N_EQUAL, "N_EQUAL"),
+        (F13, "F13"),
+        (F14, "F14"),
+        (F15, "F15"),
+        (F16, "F16"),
+        (F17, "F17"),
+        (F18, "F18"),
+        (F19, "F19"),
+        (F20, "F20"),
+        (F21, "F21"),
+        (F22, "F22"),
+        (F23, "F23"),
+        (F24, "F24"),
+        (EXECUTE, "EXECUTE"),
+        (HELP, "HELP"),
+        (KB_MENU, "KB_MENU"),
+        (SELECT, "SELECT"),
+        (STOP, "STOP"),
+        (AGAIN, "AGAIN"),
+        (UNDO, "UNDO"),
+        (CUT, "CUT"),
+        (COPY, "COPY"),
+        (PASTE, "PASTE"),
+        (FIND, "FIND"),
+        (MUTE, "MUTE"),
+        (VOL_UP, "VOL_UP"),
+        (VOL_DOWN, "VOL_DOWN"),
+        (LOCKING_CAPS, "LOCKING_CAPS"),
+        (LOCKING_NUM, "LOCKING_NUM"),
+        (LOCKING_SCRL, "LOCKING_SCRL"),
+        (N_COMMA, "N_COMMA"),
+        (N_EQUAL_AS400, "N_EQUAL_AS400"),
+        (INT1, "INT1"),
+        (INT2, "INT2"),
+        (INT3, "INT3"),
+        (INT4, "INT4"),
+        (INT5, "INT5"),
+        (INT6, "INT6"),
+        (INT7, "INT7"),
+        (INT8, "INT8"),
+        (INT9, "INT9"),
+        (LANG1, "LANG1"),
+        (LANG2, "LANG2"),
+        (LANG3, "LANG3"),
+        (LANG4, "LANG4"),
+        (LANG5, "LANG5"),
+        (LANG6, "LANG6"),
+        (LANG7, "LANG7"),
+        (LANG8, "LANG8"),
+        (LANG9, "LANG9"),
+        (ALT_ERASE, "ALT_ERASE"),
+        (SYSREQ, "SYSREQ"),
+        (CANCEL, "CANCEL"),
+        (CLEAR, "CLEAR"),
+        (PRIOR, "PRIOR"),
+        (RETURN2, "RETURN2"),
+        (SEPARATOR, "SEPARATOR"),
+        (OUT, "OUT"),
+        (OPER, "OPER"),
+        (CLEAR_AGAIN, "CLEAR_AGAIN"),
+        (CRSEL, "CRSEL"),
+        (EXSEL, "EXSEL"),
+        (N_00, "N_00"),
+        (N_000, "N_000"),
+        (THOUSANDS_SEP, "THOUSANDS_SEP"),
+        (DECIMAL_SEP, "DECIMAL_SEP"),
+        (CURRENCY, "CURRENCY"),
+        (SUB_CURRENCY, "SUB_CURRENCY"),
+        (N_OPAREN, "N_OPAREN"),
+        (N_CPAREN, "N_CPAREN"),
+        (N_OBRACE, "N_OBRACE"),
+        (N_CBRACE, "N_CBRACE"),
+        (N_TAB, "N_TAB"),
+        (N_BACKSPACE, "N_BACKSPACE"),
+        (N_A, "N_A"),
+        (N_B, "N_B"),
+        (N_C, "N_C"),
+        (N_D, "N_D"),
+        (N_E, "N_E"),
+        (N_F, "N_F"),
+        (N_XOR, "N_XOR"),
+        (N_CARET, "N_CARET"),
+        (N_PERCENT, "N_PERCENT"),
+        (N_LT, "N_LT"),
+        (N_GT, "N_GT"),
+        (N_AMP, "N_AMP"),
+        (N_AMP_AMP, "N_AMP_AMP"),
+        (N_PIPE, "N_PIPE"),
+        (N_PIPE_PIPE, "N_PIPE_PIPE"),
+        (N_COLON, "N_COLON"),
+        (N_HASH, "N_HASH"),
+        (N_SPACE, "N_SPACE"),
+        (N_AT, "N_AT"),
+        (N_BANG, "N_BANG"),
+        (N_MEM_STORE, "N_MEM_STORE"),
+        (N_MEM_RECALL, "N_MEM_RECALL"),
+        (N_MEM_CLEAR, "N_MEM_CLEAR"),
+        (N_MEM_ADD, "N_MEM_ADD"),
+        (N_MEM_SUB, "N_MEM_SUB"),
+        (N_MEM_MUL, "N_MEM_MUL"),
+        (N_MEM_DIV, "N_MEM_DIV"),
+        (N_PLUS_MINUS, "N_PLUS_MINUS"),
+        (N_CLEAR, "N_CLEAR"),
+        (N_CLEAR_ENTRY, "N_CLEAR_ENTRY"),
+        (N_BINARY, "N_BINARY"),
+        (N_OCTAL, "N_OCTAL"),
+        (N_DECIMAL, "N_DECIMAL"),
+        (N_HEX, "N_HEX"),
+        (L_CTRL, "L_CTRL"),
+        (L_SHFT, "L_SHFT"),
+        (L_ALT, "L_ALT"),
+        (L_SUPR, "L_SUPR"),
+        (R_CTRL, "R_CTRL"),
+        (R_SHFT, "R_SHFT"),
+        (R_ALT, "R_ALT"),
+        (R_SUPR, "R_SUPR"),
+        (RO, "RO"),
+        (KANA, "KANA"),
+        (YEN, "YEN"),
+        (HENKAN, "HENKAN"),
+        (MUHENKAN, "MUHENKAN"),
+        (HANGUL, "HANGUL"),
+        (HANJA, "HANJA"),
+    ];
+
+    /// The name of the constant for a usage, "RESERVED" for the usages without one. Not on AVR.
+    #[cfg(not(target_arch = "avr"))]
+    pub fn name(key: u8) -> &'static str {
+        NAMES.iter()
+            .find(|(code, _)| *code == key)
+            .map(|(_, name)| *name)
+            .unwrap_or("RESERVED")
+    }
+
+    /// The usage for the name of one of the constants in here, aliases included. Not on AVR.
+    #[cfg(not(target_arch = "avr"))]
+    pub fn from_name(name: &str) -> Option<u8> {
+        NAMES.iter()
+            .find(|(_, n)| *n == name)
+            .map(|(code, _)| *code)
+    }
+
+    pub fn is_mod(key: &u8) -> bool {
+        matches!(*key, L_CTRL | L_SHFT | L_ALT | L_SUPR | R_CTRL | R_SHFT | R_ALT | R_SUPR)
+    }
+
+    pub fn is_not_mod(key: &u8) -> bool {
+        !is_mod(key)
+    }
+
+    pub const fn to_mod_bitfield(key: u8) -> u8 {
+        match key {
+            L_CTRL => 0b00000001,
+            L_SHFT => 0b00000010,
+            L_ALT => 0b00000100,
+            L_SUPR => 0b00001000,
+            R_CTRL => 0b00010000,
+            R_SHFT => 0b00100000,
+            R_ALT => 0b01000000,
+            R_SUPR => 0b10000000,
+            _ => 0b0
+        }
+    }
+}
+/// Keys that send a usage with modifiers held, for symbols and shortcuts on a single key.
+///
+/// The modifiers only apply to the key they are on. `C(k::Z)` sends Ctrl+Z, and a key pressed
+/// while it is held does not get the Ctrl.
+#[allow(non_snake_case)]
+pub mod mods {
+    use crate::keycode::k;
+    use crate::layout::Key;
+
+    pub const L_CTRL: u8 = k::to_mod_bitfield(k::L_CTRL);
+    pub const L_SHFT: u8 = k::to_mod_bitfield(k::L_SHFT);
+    pub const L_ALT: u8 = k::to_mod_bitfield(k::L_ALT);
+    pub const L_SUPR: u8 = k::to_mod_bitfield(k::L_SUPR);
+    pub const R_CTRL: u8 = k::to_mod_bitfield(k::R_CTRL);
+    pub const R_SHFT: u8 = k::to_mod_bitfield(k::R_SHFT);
+    pub const R_ALT: u8 = k::to_mod_bitfield(k::R_ALT);
+    pub const R_SUPR: u8 = k::to_mod_bitfield(k::R_SUPR);
+
+    /// `code` with the modifiers in the `mods` bitfield.
+    pub const fn modded(mods: u8, code: u8) -> Key {
+        Key::Modded { mods, code }
+    }
+
+    pub const fn LC(code: u8) -> Key { modded(L_CTRL, code) }
+    pub const fn LS(code: u8) -> Key { modded(L_SHFT, code) }
+    pub const fn LA(code: u8) -> Key { modded(L_ALT, code) }
+    pub const fn LG(code: u8) -> Key { modded(L_SUPR, code) }
+    pub const fn RC(code: u8) -> Key { modded(R_CTRL, code) }
+    pub const fn RS(code: u8) -> Key { modded(R_SHFT, code) }
+    /// AltGr on most European layouts.
+    pub const fn RA(code: u8) -> Key { modded(R_ALT, code) }
+    pub const fn RG(code: u8) -> Key { modded(R_SUPR, code) }
+
+    pub const fn C(code: u8) -> Key { LC(code) }
+    pub const fn S(code: u8) -> Key { LS(code) }
+    pub const fn A(code: u8) -> Key { LA(code) }
+    pub const fn G(code: u8) -> Key { LG(code) }
+
+    /// Ctrl+Shift+Alt+Gui. `HYPER(k::NONE)` is a key that only holds the modifiers.
+    pub const fn HYPER(code: u8) -> Key { modded(L_CTRL | L_SHFT | L_ALT | L_SUPR, code) }
+    /// Ctrl+Shift+Alt. `MEH(k::NONE)` is a key that only holds the modifiers.
+    pub const fn MEH(code: u8) -> Key { modded(L_CTRL | L_SHFT | L_ALT, code) }
+}
+
+/// System control usages on the HID Generic Desktop page (0x01), for `Key::System`.
+pub mod system {
+    pub const POWER: u8 = 0x81;
+    pub const SLEEP: u8 = 0x82;
+    pub const WAKE: u8 = 0x83;
+}
+
+/// Usages on the HID Consumer page (0x0C), for `Key::Consumer`.
+pub mod consumer {
+    pub const BRIGHT_UP: u16 = 0x006F;
+    pub const BRIGHT_DOWN: u16 = 0x0070;
+
+    pub const FAST_FWD: u16 = 0x00B3;
+    pub const REWIND: u16 = 0x00B4;
+    pub const NEXT: u16 = 0x00B5;
+    pub const PREV: u16 = 0x00B6;
+    pub const STOP: u16 = 0x00B7;
+    pub const EJECT: u16 = 0x00B8;
+    pub const PLAY_PAUSE: u16 = 0x00CD;
+    pub const MUTE: u16 = 0x00E2;
+    pub const VOL_UP: u16 = 0x00E9;
+    pub const VOL_DOWN: u16 = 0x00EA;
+
+    pub const MEDIA_SELECT: u16 = 0x0183;
+    pub const MAIL: u16 = 0x018A;
+    pub const CALCULATOR: u16 = 0x0192;
+    pub const FILE_BROWSER: u16 = 0x0194;
+    pub const BROWSER: u16 = 0x0196;
+    pub const LOCK: u16 = 0x019E;
+    pub const CONTROL_PANEL: u16 = 0x019F;
+
+    pub const WWW_SEARCH: u16 = 0x0221;
+    pub const WWW_HOME: u16 = 0x0223;
+    pub const WWW_BACK: u16 = 0x0224;
+    pub const WWW_FORWARD: u16 = 0x0225;
+    pub const WWW_STOP: u16 = 0x0226;
+    pub const WWW_REFRESH: u16 = 0x0227;
+    pub const WWW_FAVORITES: u16 = 0x022A;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::layout::Key;
+
+    use super::{k, mods};
+    use super::k::{de, us_intl};
+    use super::k::norde::{dk, fi, no, se};
+
+    #[test]
+    fn every_usage_up_to_the_modifiers_has_a_name() {
+        let reserved = (0xA5..=0xAF).chain(0xDE..=0xDF);
+        for usage in 0..=0xE7u8 {
+            let name = k::name(usage);
+            match reserved.clone().any(|r| r == usage) {
+                true => assert_eq!(name, "RESERVED"),
+                false => assert_eq!(k::from_name(name), Some(usage), "{:#04X} {}", usage, name),
+            }
+        }
+    }
+
+    #[test]
+    fn names_match_the_constants() {
+        assert_eq!(k::name(k::A), "A");
+        assert_eq!(k::name(k::F24), "F24");
+        assert_eq!(k::name(k::R_SUPR), "R_SUPR");
+        assert_eq!(k::from_name("N_EQUAL"), Some(k::N_EQUAL));
+        assert_eq!(k::from_name("nope"), None);
+    }
+
+    #[test]
+    fn aliases_parse_but_print_as_the_usage_name() {
+        assert_eq!(k::from_name("YEN"), Some(k::INT3));
+        assert_eq!(k::from_name("HANGUL"), Some(k::LANG1));
+        assert_eq!(k::name(k::HENKAN), "INT4");
+    }
+
+    fn is(key: Key, with: u8, usage: u8) -> bool {
+        match key {
+            Key::KeyCode(code) => with == 0 && code == usage,
+            Key::Modded { mods, code } => mods == with && code == usage,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn locale_symbols_are_usage_and_modifiers_for_their_host_layout() {
+        assert!(is(se::AT, mods::R_ALT, k::K2));
+        assert!(is(se::Å, 0, k::OBRAKET));
+        assert!(is(se::QUESTION, mods::L_SHFT, k::DASH));
+        assert!(is(fi::OBRACE, mods::R_ALT, k::K7));
+        assert!(is(no::Ø, 0, k::COLON));
+        assert!(is(dk::Ø, 0, k::QUOTE));
+        assert!(is(de::Z, 0, k::Y));
+        assert!(is(de::AT, mods::R_ALT, k::Q));
+        assert!(is(us_intl::E_ACUTE, mods::R_ALT, k::E));
+        assert!(is(us_intl::AT, mods::L_SHFT, k::K2));
+    }
+}
@@ -0,0 +1,139 @@
+//! Sets of layers.
+//!
+//! A [`LayerMask`] has one bit per layer, in as many bytes as it takes. [`Layers`] is the one
+//! with room for every layer in the keymap, so adding layers only makes it wider. Bytes rather
+//! than a bigger integer because the atmega32u4 works on a byte at a time anyway.
+use crate::layout::LAYERS;
+
+/// Bytes needed for one bit per layer in the keymap. Tests have room for at least 24, so their
+/// layouts can go past a byte whatever the keymap is.
+pub const LAYER_BYTES: usize = match LAYERS.div_ceil(8) {
+    bytes if cfg!(test) && bytes < 3 => 3,
+    bytes => bytes,
+};
+
+/// A set of the layers in the keymap.
+pub type Layers = LayerMask<LAYER_BYTES>;
+
+/// A set of up to `BYTES * 8` layers.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct LayerMask<const BYTES: usize> {
+    bits: [u8; BYTES],
+}
+
+impl<const BYTES: usize> LayerMask<BYTES> {
+    pub const fn empty() -> Self {
+        Self { bits: [0; BYTES] }
+    }
+
+    pub const fn of(layers: &[u8]) -> Self {
+        let mut mask = Self::empty();
+        let mut i = 0;
+        while i < layers.len() {
+            mask.insert(layers[i]);
+            i += 1;
+        }
+        mask
+    }
+
+    pub const fn contains(&self, layer: u8) -> bool {
+        let (byte, bit) = Self::index(layer);
+        byte < BYTES && self.bits[byte] & bit != 0
+    }
+
+    /// Add a layer. Layers that don't fit are ignored.
+    pub const fn insert(&mut self, layer: u8) {
+        let (byte, bit) = Self::index(layer);
+        if byte < BYTES {
+            self.bits[byte] |= bit;
+        }
+    }
+
+    pub const fn toggle(&mut self, layer: u8) {
+        let (byte, bit) = Self::index(layer);
+        if byte < BYTES {
+            self.bits[byte] ^= bit;
+        }
+    }
+
+    pub const fn union(mut self, other: Self) -> Self {
+        let mut i = 0;
+        while i < BYTES {
+            self.bits[i] |= other.bits[i];
+            i += 1;
+        }
+        self
+    }
+
+    pub const fn intersection(mut self, other: Self) -> Self {
+        let mut i = 0;
+        while i < BYTES {
+            self.bits[i] &= other.bits[i];
+            i += 1;
+        }
+        self
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.highest().is_none()
+    }
+
+    /// The highest layer in the set.
+    pub const fn highest(&self) -> Option<u8> {
+        self.highest_below((BYTES * 8) as u16, 0)
+    }
+
+    /// The highest layer in the set that is below `layer` and not below `floor`.
+    pub const fn highest_below(&self, layer: u16, floor: u8) -> Option<u8> {
+        let mut l = layer;
+        while l > floor as u16 {
+            l -= 1;
+            if self.contains(l as u8) {
+                return Some(l as u8);
+            }
+        }
+        None
+    }
+
+    const fn index(layer: u8) -> (usize, u8) {
+        (layer as usize / 8, 1 << (layer % 8))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LayerMask;
+
+    #[test]
+    fn layers_past_the_first_byte() {
+        let mut mask = LayerMask::<3>::of(&[0, 9, 17]);
+        assert!(mask.contains(9) && mask.contains(17) && !mask.contains(8));
+        assert_eq!(mask.highest(), Some(17));
+        mask.toggle(17);
+        mask.toggle(23);
+        assert_eq!(mask.highest(), Some(23));
+        assert_eq!(mask.highest_below(23, 0), Some(9));
+        assert_eq!(mask.highest_below(9, 1), None);
+    }
+
+    #[test]
+    fn layers_that_do_not_fit_are_left_out() {
+        let mut mask = LayerMask::<2>::empty();
+        mask.insert(16);
+        mask.toggle(200);
+        assert!(mask.is_empty());
+        assert!(!mask.contains(16));
+    }
+
+    #[test]
+    fn union_has_both() {
+        let mask = LayerMask::<2>::of(&[1, 15]).union(LayerMask::of(&[3]));
+        assert_eq!(mask, LayerMask::of(&[1, 3, 15]));
+    }
+
+    #[test]
+    fn intersection_has_what_is_in_both() {
+        let mask = LayerMask::<2>::of(&[1, 3, 15]).intersection(LayerMask::of(&[3, 15, 9]));
+        assert_eq!(mask, LayerMask::of(&[3, 15]));
+    }
+}
@@ -0,0 +1,348 @@
+use avr_progmem::progmem;
+use avr_progmem::wrapper::ProgMem;
+
+use Key::Dead;
+use KeyType::{Instant, OnHold};
+
+use crate::combo::{Combo, Combos};
+use crate::combos;
+use crate::functions;
+use crate::layers::{Layers, LAYER_BYTES};
+use crate::layout;
+use crate::lint;
+use crate::mouse;
+use crate::position::position::Position;
+use crate::state::State;
+
+#[derive(Copy, Clone)]
+pub enum KeyType {
+    Instant(Key),
+    OnHold(Key, u16, Key, Option<Flavor>), // Press key, wait time in ms, hold key, flavor or the layout's
+    /// Different keys for tapping it once, twice or three times, or holding it, see `TapDance`.
+    TapDance(TapDanceRef),
+}
+
+/// How an `OnHold` picks between its tap and hold key when other keys are pressed while it is
+/// held. Held past its wait time it is always the hold key, whatever the flavor.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Flavor {
+    /// Only the wait time decides, released before it is a tap.
+    TapPreferred,
+    /// The hold key as soon as another key is pressed.
+    HoldOnOtherKeyPress,
+    /// The hold key once another key is pressed and released while it is held. A roll, where the
+    /// other key is released after it, stays a tap. Made for home row mods.
+    PermissiveHold,
+}
+
+#[derive(Copy, Clone)]
+pub enum Key {
+    KeyCode(u8),
+    /// A usage sent with the modifiers in the bitfield, see `keycode::mods`.
+    Modded { mods: u8, code: u8 },
+    /// Runs once when pressed.
+    Function(fn(&mut State)),
+    LayerMo(u8),
+    /// Turns the layer on, or off if it is already on, when pressed.
+    LayerToggle(u8),
+    /// Turns the layer on and every other toggled layer off when pressed.
+    LayerTo(u8),
+    /// Makes the layer the base that the others are on top of. Kept when the keyboard is unplugged.
+    DefaultLayer(u8),
+    /// Like `LayerMo` while held, toggles the layer when tapped `TAP_TOGGLE_TAPS` times.
+    LayerTapToggle(u8),
+    /// The modifiers in the bitfield, see `keycode::mods`, held while it is held. Tapped, they go
+    /// with the next key only, tapped twice they stay on until it is tapped again.
+    OneShotMod(u8),
+    /// Like `LayerMo` while held. Tapped, the layer is on for the next key only, tapped twice it
+    /// stays on until it is tapped again.
+    OneShotLayer(u8),
+    /// The key on the next active layer below. Nothing on the default layer.
+    Transparent,
+    /// The key the given number of layers down, whether that layer is active or not.
+    PassThrough(u8),
+    Consumer(u16),
+    System(u8),
+    Mouse(mouse::Mouse),
+    Dead,
+}
+
+/// Turns on `adjust` while both `lower` and `upper` are active, like QMK's tri layer.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct TriLayer {
+    pub lower: u8,
+    pub upper: u8,
+    pub adjust: u8,
+}
+
+impl TriLayer {
+    /// `active` with `adjust` added if both `lower` and `upper` are in it.
+    pub const fn apply(&self, mut active: Layers) -> Layers {
+        if active.contains(self.lower) && active.contains(self.upper) {
+            active.insert(self.adjust);
+        }
+        active
+    }
+}
+
+/// The keys of a `KeyType::TapDance`. It is tapped again by pressing it within `term` ms of
+/// releasing it, and ends when it isn't, when another key is pressed, or when it is held longer
+/// than `term`. `Dead` keys are left out, holding it where there is no hold key is the same as
+/// holding the tap key.
+#[derive(Copy, Clone)]
+pub struct TapDance {
+    /// The tapping term in ms.
+    pub term: u16,
+    /// The key for one, two and three taps.
+    pub taps: [Key; TAP_DANCE_TAPS],
+    /// The key for holding it on the first, second and third press.
+    pub holds: [Key; TAP_DANCE_TAPS],
+}
+
+impl TapDance {
+    pub const fn new(term: u16, taps: &[Key], holds: &[Key]) -> Self {
+        if taps.len() > TAP_DANCE_TAPS || holds.len() > TAP_DANCE_TAPS {
+            panic!("a tap dance has at most TAP_DANCE_TAPS tap and hold keys");
+        }
+        let mut dance = Self { term, taps: [Dead; TAP_DANCE_TAPS], holds: [Dead; TAP_DANCE_TAPS] };
+        let mut i = 0;
+        while i < taps.len() {
+            dance.taps[i] = taps[i];
+            i += 1;
+        }
+        let mut i = 0;
+        while i < holds.len() {
+            dance.holds[i] = holds[i];
+            i += 1;
+        }
+        dance
+    }
+
+    /// The key for tapping it `count` times.
+    pub const fn tap(&self, count: u8) -> Key {
+        match count as usize {
+            0 => Dead,
+            n if n <= TAP_DANCE_TAPS => self.taps[n - 1],
+            _ => Dead,
+        }
+    }
+
+    /// The key for holding it on press `count`.
+    pub const fn hold(&self, count: u8) -> Key {
+        match count as usize {
+            0 => Dead,
+            n if n <= TAP_DANCE_TAPS => self.holds[n - 1],
+            _ => Dead,
+        }
+    }
+}
+
+/// A `TapDance` in progmem, made by the `TD` cell of `layout!`.
+#[derive(Copy, Clone)]
+pub struct TapDanceRef(&'static TapDance);
+
+impl TapDanceRef {
+    /// # Safety
+    ///
+    /// `dance` has to be in progmem on the atmega32u4, like a `progmem!` static.
+    pub const unsafe fn new(dance: &'static TapDance) -> Self {
+        Self(dance)
+    }
+
+    pub fn load(&self) -> TapDance {
+        // SAFETY: `new` makes sure it is in progmem
+        unsafe { ProgMem::new(self.0) }.load()
+    }
+
+    /// The dance while compiling, where progmem is read like any other memory, for `lint`.
+    pub(crate) const fn in_const(&self) -> &TapDance {
+        self.0
+    }
+}
+
+impl Key {
+    /// The layer a layer key switches to, `None` for other keys.
+    pub const fn layer(&self) -> Option<u8> {
+        match self {
+            Key::LayerMo(l) | Key::LayerToggle(l) | Key::LayerTo(l) | Key::DefaultLayer(l) | Key::LayerTapToggle(l)
+            | Key::OneShotLayer(l) => Some(*l),
+            _ => None,
+        }
+    }
+}
+
+pub use crate::dimensions::{COLS, ROWS, TAP_DANCE_TAPS};
+pub const BUTTONS: usize = ROWS * COLS;
+pub const NUM_CHUNKS: usize = BUTTONS / 6;
+/// How many layers the keymap has. A keymap file sets its own, see `build.rs`.
+#[cfg(not(waddle_keymap))]
+pub const LAYERS: usize = 4;
+pub const LEDS: usize = 3;
+/// The layer each led shows. It is lit while that layer is locked on by `LayerToggle`, `LayerTo`,
+/// `LayerTapToggle` or `DefaultLayer`, as well as when it is toggled with `State::toggle_led`.
+pub const LAYER_LEDS: [Option<u8>; LEDS] = [Some(1), Some(2), Some(3)];
+// The keymap, unless one is generated from a keymap file, see `build.rs`.
+#[cfg(not(waddle_keymap))]
+pub const TRI_LAYER: Option<TriLayer> = Some(TriLayer { lower: 1, upper: 2, adjust: 3 });
+/// The flavor of the `OnHold` keys that don't have their own.
+#[cfg(not(waddle_keymap))]
+pub const FLAVOR: Flavor = Flavor::TapPreferred;
+/// Buttons pressed together for a key of their own, see `combo`.
+#[cfg(not(waddle_keymap))]
+pub const COMBOS: Combos = combos![];
+// @formatter:off
+#[cfg(not(waddle_keymap))]
+pub const KEYMAP: [[[KeyType; COLS]; ROWS]; LAYERS] = layout![
+    [
+        TAB    Q      W         E     R     T     Y      U     I     O    P        SE(Å)  |
+        ESC    A      S         D     F     G     H      J     K     L    SE(Ö)    SE(Ä)  |
+        L_SHFT Z      X         C     V     B     N      M     COMMA DOT  SE(DASH) R_SHFT |
+        L_CTRL L_SUPR BS_N_PIPE L_ALT MO(1) SPACE RETURN MO(2) R_ALT MENU R_SUPR   R_CTRL
+    ]
+    [
+        K1  K2  K3   K4  K5  K6  K7      K8      K9      K0      OBRAKET CBRAKET   |
+        ___ ___ ___  ___ ___ ___ ARROW_L ARROW_D ARROW_U ARROW_R TILDE   EQUAL     |
+        ___ ___ ___  ___ ___ ___ ___     ___     ___     ___     ___     BACKSPACE |
+        ___ ___ GACC ___ ___ ___ ___     MO(2)   ___     ___     ___     ___
+    ]
+    [
+        F1  F2            F3            F4            F5             F6     F7       F8       F9     F10       F11       F12    |
+        ___ MS(WheelLeft) MS(WheelDown) MS(WheelUp)   MS(WheelRight) INSERT HOME     PGDWN    PGUP   END       PRNT_SCRN DASH   |
+        ___ MS(Button(1)) MS(Button(3)) MS(Button(2)) ___            ___    MS(Left) MS(Down) MS(Up) MS(Right) ___       DELETE |
+        ___ ___           ___           ___           MO(1)          ___    ___      ___      ___    ___       ___       ___
+    ]
+    [
+        FN(functions::toggle_led_0) FN(functions::toggle_led_1) FN(functions::toggle_led_2) FN(functions::toggle_nkro) ___ ___ ___       ___           ___         ___       ___             SYS(SLEEP) |
+        ___                         ___                         ___                         ___                        ___ ___ CON(PREV) CON(VOL_DOWN) CON(VOL_UP) CON(NEXT) CON(PLAY_PAUSE) CON(MUTE)  |
+        ___                         ___                         ___                         ___                        ___ ___ ___       ___           ___         ___       ___             R_SHFT     |
+        ___                         ___                         ___                         ___                        ___ ___ ___       ___           ___         ___       ___             ___
+    ]
+];
+// @formatter:on
+
+#[cfg(waddle_keymap)]
+include!(concat!(env!("OUT_DIR"), "/keymap.rs"));
+
+const _: () = lint::check(&KEYMAP, TRI_LAYER, COMBOS);
+
+/// How much flash the keymap may take on the atmega32u4. It has 28 KiB next to the bootloader and
+/// the rest of the firmware needs its share of that, lower this if the firmware grows.
+pub const KEYMAP_FLASH_BUDGET: usize = 12 * 1024;
+/// Bytes a `KeyType` takes on the atmega32u4, where a function pointer is 2 bytes. Checked against
+/// `size_of` when building for it.
+const KEY_TYPE_BYTES: usize = 9;
+/// Bytes a `TapDance` takes on the atmega32u4.
+const TAP_DANCE_BYTES: usize = 20;
+/// Bytes a `Combo` takes on the atmega32u4, without the one byte of its layers for every 8.
+const COMBO_BYTES: usize = 17;
+
+/// How much flash a keymap takes on the atmega32u4: the matrix, the tap dances kept next to it
+/// and the combos.
+pub const fn keymap_bytes(layers: usize, tap_dances: usize, combos: usize) -> usize {
+    layers * ROWS * COLS * KEY_TYPE_BYTES + tap_dances * TAP_DANCE_BYTES + combos * (COMBO_BYTES + layers.div_ceil(8))
+}
+
+/// How much flash the keymap, its tap dances and combos take on the atmega32u4, whatever it is
+/// built for. The `keymap_size` example prints it.
+pub const KEYMAP_BYTES: usize = keymap_bytes(LAYERS, lint::tap_dances(&KEYMAP), COMBOS.len());
+
+const _: () = lint::check_size(KEYMAP_BYTES, KEYMAP_FLASH_BUDGET);
+
+#[cfg(target_arch = "avr")]
+const _: () = assert!(
+    core::mem::size_of::<KeyType>() == KEY_TYPE_BYTES
+        && core::mem::size_of::<TapDance>() == TAP_DANCE_BYTES
+        && core::mem::size_of::<Combo>() == COMBO_BYTES + LAYER_BYTES,
+    "the sizes in layout.rs are off, fix them for KEYMAP_BYTES"
+);
+
+progmem! {
+    pub static progmem MATRIX: [[[KeyType; COLS]; ROWS]; LAYERS] = KEYMAP;
+}
+
+pub static LAYOUT: Layout = Layout { matrix: &MATRIX, tri_layer: TRI_LAYER, flavor: FLAVOR, combos: COMBOS };
+
+/// The layers of a layout in progmem, however many there are.
+trait Matrix: Sync {
+    fn layers(&self) -> usize;
+    fn get_key(&self, layer: u8, position: &Position) -> KeyType;
+}
+
+impl<const L: usize> Matrix for ProgMem<[[[KeyType; COLS]; ROWS]; L]> {
+    fn layers(&self) -> usize {
+        L
+    }
+
+    fn get_key(&self, layer: u8, position: &Position) -> KeyType {
+        self.at(layer as usize).at(position.row() as usize).at(position.col() as usize).load()
+    }
+}
+
+pub struct Layout {
+    /// `LAYERS` layers for the keymap, the layouts of tests have as many as they need.
+    matrix: &'static dyn Matrix,
+    tri_layer: Option<TriLayer>,
+    /// The flavor of the `OnHold` keys that don't have their own.
+    flavor: Flavor,
+    combos: Combos,
+}
+
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Layout {
+    pub fn new() -> Self {
+        Self { matrix: &MATRIX, tri_layer: TRI_LAYER, flavor: FLAVOR, combos: COMBOS }
+    }
+
+    pub const fn from<const L: usize>(matrix: &'static ProgMem<[[[KeyType; COLS]; ROWS]; L]>) -> Self {
+        assert!(L <= LAYER_BYTES * 8, "a layout can't have more layers than Layers has room for");
+        Self { matrix, tri_layer: None, flavor: Flavor::TapPreferred, combos: Combos::NONE }
+    }
+
+    pub const fn with_tri_layer(self, lower: u8, upper: u8, adjust: u8) -> Self {
+        Self { tri_layer: Some(TriLayer { lower, upper, adjust }), ..self }
+    }
+
+    pub const fn with_flavor(self, flavor: Flavor) -> Self {
+        Self { flavor, ..self }
+    }
+
+    pub const fn with_combos(self, combos: Combos) -> Self {
+        Self { combos, ..self }
+    }
+
+    pub fn tri_layer(&self) -> Option<TriLayer> {
+        self.tri_layer
+    }
+
+    pub fn flavor(&self) -> Flavor {
+        self.flavor
+    }
+
+    pub fn combos(&self) -> Combos {
+        self.combos
+    }
+
+    /// How many layers there are, `LAYERS` unless it is the layout of a test.
+    pub fn layers(&self) -> usize {
+        self.matrix.layers()
+    }
+
+    pub fn get_key(&self, layer: u8, position: &Position) -> KeyType {
+        self.matrix.get_key(layer, position)
+    }
+}
+/// How many layers the layouts of tests have, whatever the keymap is built with.
+#[cfg(test)]
+pub(crate) const TEST_LAYERS: usize = 4;
+
+/// A matrix with every key `Dead`, for tests to fill in the keys they need.
+#[cfg(test)]
+pub(crate) const fn dead_matrix() -> [[[KeyType; COLS]; ROWS]; TEST_LAYERS] {
+    [[[Instant(Dead); COLS]; ROWS]; TEST_LAYERS]
+}
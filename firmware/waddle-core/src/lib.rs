@@ -0,0 +1,25 @@
+#![cfg_attr(not(test), no_std)]
+#![allow(unused)]
+
+//! Hardware independent parts of the waddle firmware.
+//!
+//! Everything in here compiles for the host as well as for the atmega32u4 so the matrix, state and
+//! layout logic can be tested with `cargo test` before flashing. The board specific bits live in
+//! the `waddle` binary, which implements [`hal::KeyboardHal`] for the pins and the USB stack.
+
+pub mod macros;
+mod dimensions;
+pub mod combo;
+pub mod controller;
+pub mod debounce;
+pub mod functions;
+pub mod hal;
+pub mod keycode;
+pub mod layers;
+pub mod layout;
+pub mod lint;
+pub mod mouse;
+pub mod position;
+pub mod report;
+pub mod scan;
+pub mod state;
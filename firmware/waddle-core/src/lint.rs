@@ -0,0 +1,549 @@
+//! Checks that a keymap can work, run on `layout::KEYMAP` and `layout::COMBOS` when they are
+//! compiled.
+//!
+//! A keymap that fails a check does not build, the error names the layer, row and column of the
+//! key, or the number of the combo. Everything in here is `const fn` for that reason.
+use crate::combo::Combos;
+use crate::layers::{Layers, LAYER_BYTES};
+use crate::layout::{COLS, Key, KeyType, ROWS, TAP_DANCE_TAPS, TriLayer};
+
+/// What is wrong with a key in a keymap.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Problem {
+    /// `PassThrough(0)`, which would look the key up on its own layer forever.
+    PassThroughToSelf,
+    /// A `PassThrough` that goes further down than layer 0.
+    PassThroughBelowBase(u8),
+    /// A layer key to a layer that does not exist.
+    NoSuchLayer(u8),
+    /// An `OnHold` that turns into the hold key as soon as it is pressed.
+    ZeroHoldTime,
+    /// A `TapDance` that ends before it can be tapped again.
+    ZeroTappingTerm,
+    /// A layer that no layer key or tri layer switches to.
+    Unreachable,
+}
+
+/// A problem with a keymap. `row` and `col` are `None` for problems with a whole layer.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Lint {
+    pub layer: u8,
+    pub row: Option<u8>,
+    pub col: Option<u8>,
+    pub problem: Problem,
+}
+
+/// What is wrong with a combo.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum ComboProblem {
+    /// Fewer than two buttons, which is just a key.
+    TooFewButtons,
+    /// A button that is in it more than once.
+    SameButtonTwice,
+    /// A button past `ROWS` or `COLS`.
+    OutsideMatrix,
+    /// A layer key to, or a restriction to, a layer that does not exist.
+    NoSuchLayer(u8),
+    /// `Transparent`, `PassThrough` or `Dead`, which only mean something in the matrix.
+    NotAKey,
+}
+
+/// A problem with the combo at index `combo`.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct ComboLint {
+    pub combo: usize,
+    pub problem: ComboProblem,
+}
+
+/// The first problem found in `keymap`, if any. `combos` are only looked at for the layers they
+/// switch to, see `lint_combos` for what is wrong with them.
+pub const fn lint<const L: usize>(keymap: &[[[KeyType; COLS]; ROWS]; L], tri_layer: Option<TriLayer>, combos: Combos) -> Result<(), Lint> {
+    if let Some(TriLayer { lower, upper, adjust }) = tri_layer {
+        let layers = [lower, upper, adjust];
+        let mut i = 0;
+        while i < layers.len() {
+            if layers[i] as usize >= L {
+                return Err(Lint { layer: layers[i], row: None, col: None, problem: Problem::NoSuchLayer(layers[i]) });
+            }
+            i += 1;
+        }
+    }
+
+    let mut layer = 0;
+    while layer < L {
+        let mut row = 0;
+        while row < ROWS {
+            let mut col = 0;
+            while col < COLS {
+                let problem = match keymap[layer][row][col] {
+                    KeyType::OnHold(_, 0, _, _) => Some(Problem::ZeroHoldTime),
+                    KeyType::TapDance(dance) if dance.in_const().term == 0 => Some(Problem::ZeroTappingTerm),
+                    key_type => check_keys(&cell_keys(&key_type), layer, L),
+                };
+                if let Some(problem) = problem {
+                    return Err(Lint { layer: layer as u8, row: Some(row as u8), col: Some(col as u8), problem });
+                }
+                col += 1;
+            }
+            row += 1;
+        }
+        layer += 1;
+    }
+
+    let reachable = reachable_layers(keymap, tri_layer, combos);
+    let mut layer = 1;
+    while layer < L {
+        if !reachable.contains(layer as u8) {
+            return Err(Lint { layer: layer as u8, row: None, col: None, problem: Problem::Unreachable });
+        }
+        layer += 1;
+    }
+    Ok(())
+}
+
+/// The first problem found in `combos` for a keymap with `layers` layers, if any.
+pub const fn lint_combos(combos: Combos, layers: usize) -> Result<(), ComboLint> {
+    let mut i = 0;
+    while i < combos.len() {
+        if let Some(problem) = combo_problem(combos, i, layers) {
+            return Err(ComboLint { combo: i, problem });
+        }
+        i += 1;
+    }
+    Ok(())
+}
+
+const fn combo_problem(combos: Combos, i: usize, layers: usize) -> Option<ComboProblem> {
+    let combo = combos.in_const(i);
+    let len = combo.size();
+    if len < 2 {
+        return Some(ComboProblem::TooFewButtons);
+    }
+    let mut b = 0;
+    while b < len {
+        let Some(button) = combo.buttons[b] else { return Some(ComboProblem::TooFewButtons) };
+        if button.row() as usize >= ROWS || button.col() as usize >= COLS {
+            return Some(ComboProblem::OutsideMatrix);
+        }
+        let mut other = 0;
+        while other < b {
+            if let Some(o) = combo.buttons[other] {
+                if o.index() == button.index() {
+                    return Some(ComboProblem::SameButtonTwice);
+                }
+            }
+            other += 1;
+        }
+        b += 1;
+    }
+    if let Some(on) = combo.layers {
+        let mut layer = layers;
+        while layer < u8::MAX as usize + 1 {
+            if on.contains(layer as u8) {
+                return Some(ComboProblem::NoSuchLayer(layer as u8));
+            }
+            layer += 1;
+        }
+    }
+    match combo.key {
+        Key::Transparent | Key::PassThrough(_) | Key::Dead => Some(ComboProblem::NotAKey),
+        key => match key.layer() {
+            Some(to) if to as usize >= layers => Some(ComboProblem::NoSuchLayer(to)),
+            _ => None,
+        },
+    }
+}
+
+/// Fail the build with a message for the first problem in `keymap` or `combos`.
+pub const fn check<const L: usize>(keymap: &[[[KeyType; COLS]; ROWS]; L], tri_layer: Option<TriLayer>, combos: Combos) {
+    if let Err(lint) = lint(keymap, tri_layer, combos) {
+        let message = lint.message();
+        panic!("{}", message.as_str());
+    }
+    if let Err(lint) = lint_combos(combos, L) {
+        let message = lint.message();
+        panic!("{}", message.as_str());
+    }
+}
+
+/// Fail the build if the keymap takes more than `budget` bytes of flash.
+pub const fn check_size(bytes: usize, budget: usize) {
+    if bytes > budget {
+        let message = Message::new().push("keymap takes ").push_num(bytes).push(" bytes of flash, KEYMAP_FLASH_BUDGET is ")
+            .push_num(budget);
+        panic!("{}", message.as_str());
+    }
+}
+
+/// How many `TapDance` keys there are in `keymap`, each with its own static.
+pub const fn tap_dances<const L: usize>(keymap: &[[[KeyType; COLS]; ROWS]; L]) -> usize {
+    let mut count = 0;
+    let mut layer = 0;
+    while layer < L {
+        let mut row = 0;
+        while row < ROWS {
+            let mut col = 0;
+            while col < COLS {
+                if let KeyType::TapDance(_) = keymap[layer][row][col] {
+                    count += 1;
+                }
+                col += 1;
+            }
+            row += 1;
+        }
+        layer += 1;
+    }
+    count
+}
+
+const fn check_keys(keys: &[Key], layer: usize, layers: usize) -> Option<Problem> {
+    let mut i = 0;
+    while i < keys.len() {
+        if let Some(problem) = check_key(keys[i], layer, layers) {
+            return Some(problem);
+        }
+        i += 1;
+    }
+    None
+}
+
+const fn check_key(key: Key, layer: usize, layers: usize) -> Option<Problem> {
+    match key {
+        Key::PassThrough(0) => Some(Problem::PassThroughToSelf),
+        Key::PassThrough(down) if down as usize > layer => Some(Problem::PassThroughBelowBase(down)),
+        _ => match key.layer() {
+            Some(to) if to as usize >= layers => Some(Problem::NoSuchLayer(to)),
+            _ => None,
+        },
+    }
+}
+
+/// Bitmask of the layers that can be switched to, from the base layer through the layer keys
+/// on the layers that can be reached, through the combos that work on them and through the tri
+/// layer.
+const fn reachable_layers<const L: usize>(keymap: &[[[KeyType; COLS]; ROWS]; L], tri_layer: Option<TriLayer>, combos: Combos) -> Layers {
+    let mut reachable = Layers::of(&[0]);
+    loop {
+        let mut next = reachable;
+        let mut layer = 0;
+        while layer < L {
+            if reachable.contains(layer as u8) {
+                next = next.union(layer_keys(&keymap[layer]));
+            }
+            layer += 1;
+        }
+        next = next.union(combo_layers(combos, &reachable));
+        if let Some(tri_layer) = tri_layer {
+            next = tri_layer.apply(next);
+        }
+        if eq(&next, &reachable) {
+            return reachable;
+        }
+        reachable = next;
+    }
+}
+
+/// The layers the layer keys on one layer switch to.
+const fn layer_keys(layer: &[[KeyType; COLS]; ROWS]) -> Layers {
+    let mut layers = Layers::empty();
+    let mut row = 0;
+    while row < ROWS {
+        let mut col = 0;
+        while col < COLS {
+            let keys = cell_keys(&layer[row][col]);
+            let mut i = 0;
+            while i < keys.len() {
+                if let Some(to) = keys[i].layer() {
+                    layers.insert(to);
+                }
+                i += 1;
+            }
+            col += 1;
+        }
+        row += 1;
+    }
+    layers
+}
+
+/// The layers the combos that work on any of `from` switch to.
+const fn combo_layers(combos: Combos, from: &Layers) -> Layers {
+    let mut layers = Layers::empty();
+    let mut i = 0;
+    while i < combos.len() {
+        let combo = combos.in_const(i);
+        if let Some(to) = combo.key.layer() {
+            let works = match combo.layers {
+                Some(on) => !on.intersection(*from).is_empty(),
+                None => true,
+            };
+            if works {
+                layers.insert(to);
+            }
+        }
+        i += 1;
+    }
+    layers
+}
+
+/// Every key a cell can turn into, `Dead` for the rest.
+const fn cell_keys(key_type: &KeyType) -> [Key; 2 * TAP_DANCE_TAPS] {
+    let mut keys = [Key::Dead; 2 * TAP_DANCE_TAPS];
+    match key_type {
+        KeyType::Instant(key) => keys[0] = *key,
+        KeyType::OnHold(tap, _, hold, _) => {
+            keys[0] = *tap;
+            keys[1] = *hold;
+        }
+        KeyType::TapDance(dance) => {
+            let dance = dance.in_const();
+            let mut i = 0;
+            while i < TAP_DANCE_TAPS {
+                keys[i] = dance.taps[i];
+                keys[TAP_DANCE_TAPS + i] = dance.holds[i];
+                i += 1;
+            }
+        }
+    }
+    keys
+}
+
+/// `==` for `Layers`, which can't be used in a `const fn`.
+const fn eq(a: &Layers, b: &Layers) -> bool {
+    let mut layer = 0;
+    while layer < LAYER_BYTES * 8 {
+        if a.contains(layer as u8) != b.contains(layer as u8) {
+            return false;
+        }
+        layer += 1;
+    }
+    true
+}
+
+impl Problem {
+    const fn describe(&self) -> &'static str {
+        match self {
+            Problem::PassThroughToSelf => "PassThrough(0) never reaches another layer",
+            Problem::PassThroughBelowBase(_) => "PassThrough goes below layer 0",
+            Problem::NoSuchLayer(_) => "switches to a layer past LAYERS",
+            Problem::ZeroHoldTime => "OnHold with a hold time of 0 ms can never be tapped",
+            Problem::ZeroTappingTerm => "TapDance with a tapping term of 0 ms can never be tapped twice",
+            Problem::Unreachable => "no layer key or tri layer switches to this layer",
+        }
+    }
+}
+
+impl ComboProblem {
+    const fn describe(&self) -> &'static str {
+        match self {
+            ComboProblem::TooFewButtons => "a combo needs at least two buttons",
+            ComboProblem::SameButtonTwice => "has the same button twice",
+            ComboProblem::OutsideMatrix => "has a button past ROWS or COLS",
+            ComboProblem::NoSuchLayer(_) => "has a layer past LAYERS",
+            ComboProblem::NotAKey => "Transparent, PassThrough and Dead only work in the matrix",
+        }
+    }
+}
+
+impl ComboLint {
+    /// `combo 2: ...`, built without `format!` so it works in a `const`.
+    pub const fn message(&self) -> Message {
+        Message::new().push("combo ").push_num(self.combo).push(": ").push(self.problem.describe())
+    }
+}
+
+impl Lint {
+    /// `keymap layer 1, row 2, col 3: ...`, built without `format!` so it works in a `const`.
+    pub const fn message(&self) -> Message {
+        let mut m = Message::new();
+        m = m.push("keymap layer ").push_num(self.layer as usize);
+        if let (Some(row), Some(col)) = (self.row, self.col) {
+            m = m.push(", row ").push_num(row as usize).push(", col ").push_num(col as usize);
+        }
+        m.push(": ").push(self.problem.describe())
+    }
+}
+
+/// A short message built at compile time.
+pub struct Message {
+    buf: [u8; 96],
+    len: usize,
+}
+
+impl Message {
+    pub(crate) const fn new() -> Self {
+        Self { buf: [0; 96], len: 0 }
+    }
+
+    pub(crate) const fn push(mut self, s: &str) -> Self {
+        let bytes = s.as_bytes();
+        let mut i = 0;
+        while i < bytes.len() && self.len < self.buf.len() {
+            self.buf[self.len] = bytes[i];
+            self.len += 1;
+            i += 1;
+        }
+        self
+    }
+
+    pub(crate) const fn push_num(mut self, n: usize) -> Self {
+        if n >= 10 {
+            self = self.push_num(n / 10);
+        }
+        self.push_digit((n % 10) as u8)
+    }
+
+    const fn push_digit(self, d: u8) -> Self {
+        let digit = [b'0' + d];
+        match core::str::from_utf8(&digit) {
+            Ok(s) => self.push(s),
+            Err(_) => self,
+        }
+    }
+
+    pub const fn as_str(&self) -> &str {
+        match core::str::from_utf8(self.buf.split_at(self.len).0) {
+            Ok(s) => s,
+            Err(_) => "keymap has a problem",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::combo::Combos;
+    use crate::keycode::k;
+    use crate::layout::{dead_matrix, keymap_bytes, Key, KeyType, TriLayer, TEST_LAYERS};
+
+    use super::{check_size, lint, lint_combos, tap_dances, ComboLint, ComboProblem, Lint, Problem};
+
+    fn lint_at(layer: u8, row: u8, col: u8, problem: Problem) -> Result<(), Lint> {
+        Err(Lint { layer, row: Some(row), col: Some(col), problem })
+    }
+
+    #[test]
+    fn default_keymap_is_fine() {
+        assert_eq!(lint(&crate::layout::KEYMAP, crate::layout::TRI_LAYER, crate::layout::COMBOS), Ok(()));
+    }
+
+    #[test]
+    fn pass_through_on_layer_0_is_rejected() {
+        let mut m = dead_matrix();
+        m[0][1][2] = KeyType::Instant(Key::PassThrough(1));
+        assert_eq!(lint(&m, None, Combos::NONE), lint_at(0, 1, 2, Problem::PassThroughBelowBase(1)));
+    }
+
+    #[test]
+    fn pass_through_past_layer_0_is_rejected_in_hold_keys_too() {
+        let mut m = dead_matrix();
+        m[0][0][0] = KeyType::Instant(Key::LayerMo(1));
+        m[1][3][4] = KeyType::OnHold(Key::KeyCode(k::A), 200, Key::PassThrough(2), None);
+        assert_eq!(lint(&m, None, Combos::NONE), lint_at(1, 3, 4, Problem::PassThroughBelowBase(2)));
+    }
+
+    #[test]
+    fn layer_mo_past_the_last_layer_is_rejected() {
+        let mut m = dead_matrix();
+        m[0][2][11] = KeyType::Instant(Key::LayerMo(4));
+        assert_eq!(lint(&m, None, Combos::NONE), lint_at(0, 2, 11, Problem::NoSuchLayer(4)));
+    }
+
+    #[test]
+    fn zero_hold_time_is_rejected() {
+        let mut m = dead_matrix();
+        m[0][0][5] = KeyType::OnHold(Key::KeyCode(k::ESC), 0, Key::KeyCode(k::L_CTRL), None);
+        assert_eq!(lint(&m, None, Combos::NONE), lint_at(0, 0, 5, Problem::ZeroHoldTime));
+    }
+
+    #[test]
+    fn layers_are_reached_through_layer_mo_on_reachable_layers() {
+        let mut m = dead_matrix();
+        m[0][3][4] = KeyType::Instant(Key::LayerMo(1));
+        m[2][3][4] = KeyType::Instant(Key::LayerMo(3));
+        assert_eq!(lint(&m, None, Combos::NONE), Err(Lint { layer: 2, row: None, col: None, problem: Problem::Unreachable }));
+        m[1][3][7] = KeyType::OnHold(Key::KeyCode(k::SPACE), 200, Key::LayerMo(2), None);
+        assert_eq!(lint(&m, None, Combos::NONE), Ok(()));
+    }
+
+    #[test]
+    fn tri_layer_reaches_its_adjust_layer() {
+        let mut m = dead_matrix();
+        m[0][3][4] = KeyType::Instant(Key::LayerMo(1));
+        m[0][3][7] = KeyType::Instant(Key::LayerMo(2));
+        assert_eq!(lint(&m, None, Combos::NONE), Err(Lint { layer: 3, row: None, col: None, problem: Problem::Unreachable }));
+        let tri_layer = TriLayer { lower: 1, upper: 2, adjust: 3 };
+        assert_eq!(lint(&m, Some(tri_layer), Combos::NONE), Ok(()));
+        let tri_layer = TriLayer { lower: 1, upper: 2, adjust: 4 };
+        assert_eq!(lint(&m, Some(tri_layer), Combos::NONE), Err(Lint { layer: 4, row: None, col: None, problem: Problem::NoSuchLayer(4) }));
+    }
+
+    #[test]
+    fn zero_tapping_term_is_rejected() {
+        let mut m = dead_matrix();
+        m[0][2][3] = crate::layout_cell!(TD(A B, XXX, 0ms));
+        assert_eq!(lint(&m, None, Combos::NONE), lint_at(0, 2, 3, Problem::ZeroTappingTerm));
+    }
+
+    #[test]
+    fn keys_in_a_tap_dance_are_checked_and_reach_layers() {
+        let mut m = dead_matrix();
+        m[0][0][0] = crate::layout_cell!(TD(A B, XXX MO(1), 200ms));
+        m[1][0][0] = crate::layout_cell!(TD(A B C, MO(2) MO(3), 200ms));
+        assert_eq!(lint(&m, None, Combos::NONE), Ok(()));
+        m[2][1][1] = crate::layout_cell!(TD(A, PT(3), 200ms));
+        assert_eq!(lint(&m, None, Combos::NONE), lint_at(2, 1, 1, Problem::PassThroughBelowBase(3)));
+    }
+
+    #[test]
+    fn combos_reach_layers_from_the_layers_they_work_on() {
+        let mut m = dead_matrix();
+        m[0][3][4] = KeyType::Instant(Key::LayerMo(1));
+        let combos = crate::combos![50ms; (0, 0) (0, 1) => MO(2), (0, 0) (0, 2) => TG(3) layers(2)];
+        assert_eq!(lint(&m, None, combos), Ok(()));
+        let combos = crate::combos![50ms; (0, 0) (0, 1) => MO(2), (0, 0) (0, 2) => TG(3) layers(3)];
+        assert_eq!(lint(&m, None, combos), Err(Lint { layer: 3, row: None, col: None, problem: Problem::Unreachable }));
+    }
+
+    #[test]
+    fn broken_combos_are_rejected() {
+        let combo_at = |combo, problem| Err(ComboLint { combo, problem });
+        assert_eq!(lint_combos(crate::combos![50ms; (0, 0) (0, 1) => ESC, (1, 1) => ESC], TEST_LAYERS), combo_at(1, ComboProblem::TooFewButtons));
+        assert_eq!(lint_combos(crate::combos![50ms; (0, 0) (0, 0) => ESC], TEST_LAYERS), combo_at(0, ComboProblem::SameButtonTwice));
+        assert_eq!(lint_combos(crate::combos![50ms; (0, 0) (4, 0) => ESC], TEST_LAYERS), combo_at(0, ComboProblem::OutsideMatrix));
+        assert_eq!(lint_combos(crate::combos![50ms; (0, 0) (0, 1) => MO(4)], TEST_LAYERS), combo_at(0, ComboProblem::NoSuchLayer(4)));
+        assert_eq!(lint_combos(crate::combos![50ms; (0, 0) (0, 1) => ESC layers(1, 6)], TEST_LAYERS), combo_at(0, ComboProblem::NoSuchLayer(6)));
+        assert_eq!(lint_combos(crate::combos![50ms; (0, 0) (0, 1) => ___], TEST_LAYERS), combo_at(0, ComboProblem::NotAKey));
+        assert_eq!(lint_combos(Combos::NONE, TEST_LAYERS), Ok(()));
+    }
+
+    #[test]
+    fn combo_message_names_the_combo() {
+        let lint = ComboLint { combo: 3, problem: ComboProblem::SameButtonTwice };
+        assert_eq!(lint.message().as_str(), "combo 3: has the same button twice");
+    }
+
+    #[test]
+    fn message_names_the_key() {
+        let lint = Lint { layer: 1, row: Some(2), col: Some(11), problem: Problem::ZeroHoldTime };
+        assert_eq!(
+            lint.message().as_str(),
+            "keymap layer 1, row 2, col 11: OnHold with a hold time of 0 ms can never be tapped"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "keymap takes 12300 bytes of flash, KEYMAP_FLASH_BUDGET is 12288")]
+    fn keymap_over_budget_is_rejected() {
+        check_size(12_288, 12_288);
+        check_size(12_300, 12_288);
+    }
+
+    #[test]
+    fn keymap_size_counts_tap_dances_and_combos() {
+        let mut m = dead_matrix();
+        m[0][0][0] = crate::layout_cell!(TD(A B, XXX, 200ms));
+        m[1][2][3] = crate::layout_cell!(TD(C, D, 200ms));
+        assert_eq!(tap_dances(&m), 2);
+        assert_eq!(keymap_bytes(4, 2, 3), 4 * 48 * 9 + 2 * 20 + 3 * 18);
+        assert_eq!(keymap_bytes(20, 0, 1), 20 * 48 * 9 + 20);
+    }
+}
@@ -0,0 +1,419 @@
+use crate::layout::{Key, KeyType, COLS, ROWS};
+use crate::lint::Message;
+
+#[macro_export]
+macro_rules! count_tts {
+    () => { 0usize };
+    ($_head:tt) => {1usize};
+    ($_head:tt, $($tail:tt),*) => {1usize + $crate::count_tts!($($tail),*)};
+}
+
+#[macro_export]
+macro_rules! vec {
+    ( $( $x:expr ),* $(,)?) => {{
+        const C: usize = $crate::count_tts!($($x),*);
+        let mut temp_vec: heapless::Vec<_, C> = heapless::Vec::new();
+        $(
+            let _ = temp_vec.push($x);
+        )*
+        temp_vec
+    }}
+}
+
+#[macro_export]
+macro_rules! rvec {
+    ($x:expr, $times:expr) => {{
+        let mut tmp: heapless::Vec<_, $times> = heapless::Vec::new();
+        for i in 0..$times {
+            tmp.push($x);
+        }
+        tmp
+    }}
+}
+
+/// The matrix of a layout, written as a grid of key names.
+///
+/// Each layer is a `[ ]` block with its rows separated by `|`. A cell is one of
+///
+/// | Cell                     | Key                                                         |
+/// |--------------------------|-------------------------------------------------------------|
+/// | `Q`, `L_CTRL`, `F13`     | `KeyCode` of the constant with that name in `keycode::k`    |
+/// | `___`                    | `Transparent`, the key on the next active layer below       |
+/// | `XXX`                    | `Dead`                                                      |
+/// | `MO(1)`                  | `LayerMo(1)`                                                |
+/// | `TG(1)`                  | `LayerToggle(1)`                                            |
+/// | `TO(1)`                  | `LayerTo(1)`                                                |
+/// | `DF(1)`                  | `DefaultLayer(1)`                                           |
+/// | `TT(1)`                  | `LayerTapToggle(1)`                                         |
+/// | `PT(2)`                  | `PassThrough(2)`                                            |
+/// | `OSM(L_CTRL \| L_SHFT)`  | `OneShotMod` with modifiers from `keycode::mods`            |
+/// | `OSL(1)`                 | `OneShotLayer(1)`                                           |
+/// | `LS(K2)`, `C(Z)`, ...    | `Modded` keys, see `keycode::mods`                          |
+/// | `SE(AT)`, `DE(Z)`, ...   | Locale symbols, `SE` `NO` `DK` `FI` `DE` `USI` (US Intl.)   |
+/// | `CON(VOL_UP)`            | `Consumer` with a usage from `keycode::consumer`            |
+/// | `SYS(SLEEP)`             | `System` with a usage from `keycode::system`                |
+/// | `MS(Left)`               | `Mouse`, see `mouse::Mouse`                                 |
+/// | `FN(\|s\| ...)`          | `Function`                                                  |
+/// | `KEY(expr)`              | Any `Key`                                                   |
+/// | `HT(ESC, L_CTRL, 200ms)` | `OnHold` with the tap key, hold key and hold time           |
+/// | `TD(A B, XXX C, 200ms)`  | `TapDance` with the tap keys, hold keys and tapping term    |
+///
+/// The tap and hold keys of `HT` can be any of the other cells. A `layout::Flavor` can follow the
+/// hold time, as in `HT(F, L_SHFT, 200ms, PermissiveHold)`, otherwise the layout's is used.
+///
+/// `TD` takes up to `TAP_DANCE_TAPS` keys for one, two and three taps, then the keys for holding
+/// it on the first, second and third press, `XXX` where there are none. `TD(COLON LS(COLON), ESC,
+/// 200ms)` is `;` tapped once, `:` tapped twice and `Esc` while held.
+///
+/// The result is a `[[[KeyType; COLS]; ROWS]; N]` for the `N` layers written. The rows of each
+/// layer and the keys of each row are checked against `ROWS` and `COLS`, so one too many or too
+/// few fails the build with the layer, row and column it is in, counting from 0:
+///
+/// ```text
+/// layout!: layer 1, row 2 has 11 keys, expected COLS = 12, col 11 is missing
+/// ```
+///
+/// A cell that is none of the above fails it with the cell, like `HT(ESC L_CTRL)` without its
+/// commas or hold time.
+///
+/// ```ignore
+/// progmem! {
+///     pub static progmem MATRIX: [[[KeyType; COLS]; ROWS]; LAYERS] = layout![
+///         [
+///             TAB    Q  W  E ...  |
+///             ...
+///         ]
+///         ...
+///     ];
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use waddle_core::layout;
+/// # use waddle_core::layout::{KeyType, COLS, ROWS};
+/// // A key short in the last row
+/// const MATRIX: [[[KeyType; COLS]; ROWS]; 1] = layout![
+///     [ XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX | XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX |
+///       XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX | XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX ]
+/// ];
+/// ```
+///
+/// ```compile_fail
+/// # use waddle_core::layout;
+/// # use waddle_core::layout::{KeyType, COLS, ROWS};
+/// // Not a cell
+/// const MATRIX: [[[KeyType; COLS]; ROWS]; 1] = layout![
+///     [ HT(ESC L_CTRL) XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX | XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX |
+///       XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX | XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX ]
+/// ];
+/// ```
+#[macro_export]
+macro_rules! layout {
+    ( $( [ $( $( $name:ident $( ( $($args:tt)* ) )? )+ )|+ ] )+ ) => {{
+        const _: () = $crate::macros::check_layout(&[ $( &[ $( [ $( stringify!($name) ),+ ].len() ),+ ] ),+ ]);
+        [ $( $crate::macros::layer([ $( $crate::macros::row([ $( $crate::layout_cell!($name $( ( $($args)* ) )?) ),+ ]) ),+ ]) ),+ ]
+    }};
+}
+
+/// One cell of a [`layout!`], as a `KeyType`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! layout_cell {
+    (HT( $tap:ident $( ( $($tap_args:tt)* ) )? , $hold:ident $( ( $($hold_args:tt)* ) )? , $ms:tt $( , $flavor:ident )? )) => {
+        $crate::layout::KeyType::OnHold(
+            $crate::layout_key!($tap $( ( $($tap_args)* ) )?),
+            $crate::macros::parse_ms(stringify!($ms)),
+            $crate::layout_key!($hold $( ( $($hold_args)* ) )?),
+            $crate::layout_flavor!($( $flavor )?),
+        )
+    };
+    (TD( $( $tap:ident $( ( $($tap_args:tt)* ) )? )+ , $( $hold:ident $( ( $($hold_args:tt)* ) )? )+ , $ms:tt )) => {
+        $crate::layout::KeyType::TapDance({
+            // Where `progmem!` puts its statics
+            #[cfg_attr(target_arch = "avr", link_section = ".progmem.data")]
+            static TAP_DANCE: $crate::layout::TapDance = $crate::layout::TapDance::new(
+                $crate::macros::parse_ms(stringify!($ms)),
+                &[ $( $crate::layout_key!($tap $( ( $($tap_args)* ) )?) ),+ ],
+                &[ $( $crate::layout_key!($hold $( ( $($hold_args)* ) )?) ),+ ],
+            );
+            // SAFETY: it is in progmem
+            unsafe { $crate::layout::TapDanceRef::new(&TAP_DANCE) }
+        })
+    };
+    ($name:ident $( ( $($args:tt)* ) )?) => {
+        $crate::layout::KeyType::Instant($crate::layout_key!($name $( ( $($args)* ) )?))
+    };
+}
+
+/// The flavor of an `HT` cell, `None` for the layout's.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! layout_flavor {
+    () => { None };
+    ($flavor:ident) => { Some($crate::layout::Flavor::$flavor) };
+}
+
+/// One key of a [`layout!`] cell, as a `Key`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! layout_key {
+    (___) => { $crate::layout::Key::Transparent };
+    (XXX) => { $crate::layout::Key::Dead };
+    (MO($layer:expr)) => { $crate::layout::Key::LayerMo($layer) };
+    (TG($layer:expr)) => { $crate::layout::Key::LayerToggle($layer) };
+    (TO($layer:expr)) => { $crate::layout::Key::LayerTo($layer) };
+    (DF($layer:expr)) => { $crate::layout::Key::DefaultLayer($layer) };
+    (TT($layer:expr)) => { $crate::layout::Key::LayerTapToggle($layer) };
+    (PT($down:expr)) => { $crate::layout::Key::PassThrough($down) };
+    (OSM($($mods:ident)|+)) => { $crate::layout::Key::OneShotMod(0 $(| $crate::keycode::mods::$mods)+) };
+    (OSL($layer:expr)) => { $crate::layout::Key::OneShotLayer($layer) };
+    (FN($f:expr)) => { $crate::layout::Key::Function($f) };
+    (KEY($key:expr)) => { $key };
+    (CON($usage:ident)) => { $crate::layout::Key::Consumer($crate::keycode::consumer::$usage) };
+    (SYS($usage:ident)) => { $crate::layout::Key::System($crate::keycode::system::$usage) };
+    (MS($($action:tt)+)) => { $crate::layout::Key::Mouse($crate::mouse::Mouse::$($action)+) };
+    (SE($symbol:ident)) => { $crate::keycode::k::norde::se::$symbol };
+    (NO($symbol:ident)) => { $crate::keycode::k::norde::no::$symbol };
+    (DK($symbol:ident)) => { $crate::keycode::k::norde::dk::$symbol };
+    (FI($symbol:ident)) => { $crate::keycode::k::norde::fi::$symbol };
+    (DE($symbol:ident)) => { $crate::keycode::k::de::$symbol };
+    (USI($symbol:ident)) => { $crate::keycode::k::us_intl::$symbol };
+    ($mods:ident($code:ident)) => { $crate::keycode::mods::$mods($crate::keycode::k::$code) };
+    ($code:ident) => { $crate::layout::Key::KeyCode($crate::keycode::k::$code) };
+    ($($cell:tt)*) => {
+        compile_error!(concat!("layout!: `", stringify!($($cell)*), "` is not a cell, see the table in the layout! docs"))
+    };
+}
+
+/// The combos of a layout, as `combo::Combos` kept in progmem.
+///
+/// The combo term can come first, otherwise it is `combo::COMBO_TERM_MS`, then the combos separated
+/// by commas. Each combo is the `(row, col)` of its buttons, `=>` and its key, written like a
+/// [`layout!`] cell without `HT` or `TD`. It can be followed by `layers(..)`, the layers it works
+/// on, and `release(All)` to keep its key held until all of its buttons are released rather than
+/// the first. `combos![]` is no combos.
+///
+/// ```ignore
+/// pub const COMBOS: Combos = combos![50ms;
+///     (1, 1) (1, 2) => ESC,
+///     (1, 1) (1, 2) (1, 3) => LC(Z) layers(0),
+///     (3, 4) (3, 7) => MO(3) release(All),
+/// ];
+/// ```
+#[macro_export]
+macro_rules! combos {
+    () => { $crate::combo::Combos::NONE };
+    (@term $term:expr; $( $( ( $row:expr, $col:expr ) )+ => $name:ident $( ( $($args:tt)* ) )? $( layers( $($layer:expr),* ) )? $( release( $release:ident ) )? ),* $(,)? ) => {{
+        // Where `progmem!` puts its statics
+        #[cfg_attr(target_arch = "avr", link_section = ".progmem.data")]
+        static COMBOS: [$crate::combo::Combo; $crate::count_tts!($($name),*)] = [
+            $(
+                $crate::combo::Combo::new(&[ $( ($row, $col) ),+ ], $crate::layout_key!($name $( ( $($args)* ) )?))
+                    $( .on_layers(&[ $($layer),* ]) )?
+                    $( .release_on($crate::combo::ComboRelease::$release) )?
+            ),*
+        ];
+        // SAFETY: it is in progmem
+        unsafe { $crate::combo::Combos::new(&COMBOS, $term) }
+    }};
+    ( $ms:tt; $($combos:tt)* ) => {
+        $crate::combos!(@term $crate::macros::parse_ms(stringify!($ms)); $($combos)*)
+    };
+    ( $($combos:tt)+ ) => {
+        $crate::combos!(@term $crate::combo::COMBO_TERM_MS; $($combos)+)
+    };
+}
+
+/// Check the rows of each layer of a [`layout!`], given as how many keys they have, against
+/// `ROWS` and `COLS`. Panics, failing the build, naming the first layer or row that is off.
+#[doc(hidden)]
+pub const fn check_layout(layers: &[&[usize]]) {
+    let mut layer = 0;
+    while layer < layers.len() {
+        let rows = layers[layer];
+        if rows.len() != ROWS {
+            let message = Message::new().push("layout!: layer ").push_num(layer).push(" has ").push_num(rows.len())
+                .push(" rows, expected ROWS = ").push_num(ROWS);
+            panic!("{}", message.as_str());
+        }
+        let mut row = 0;
+        while row < rows.len() {
+            if rows[row] != COLS {
+                let message = Message::new().push("layout!: layer ").push_num(layer).push(", row ").push_num(row)
+                    .push(" has ").push_num(rows[row]).push(" keys, expected COLS = ").push_num(COLS);
+                let message = match rows[row] < COLS {
+                    true => message.push(", col ").push_num(rows[row]).push(" is missing"),
+                    false => message.push(", col ").push_num(COLS).push(" is one too many"),
+                };
+                panic!("{}", message.as_str());
+            }
+            row += 1;
+        }
+        layer += 1;
+    }
+}
+
+/// A row of a [`layout!`] as `COLS` keys. The length is checked by `check_layout`, this only
+/// keeps a wrong one from being a type error about the whole matrix first.
+#[doc(hidden)]
+pub const fn row<const N: usize>(keys: [KeyType; N]) -> [KeyType; COLS] {
+    let mut row = [KeyType::Instant(Key::Dead); COLS];
+    let mut i = 0;
+    while i < N && i < COLS {
+        row[i] = keys[i];
+        i += 1;
+    }
+    row
+}
+
+/// A layer of a [`layout!`] as `ROWS` rows, like `row`.
+#[doc(hidden)]
+pub const fn layer<const N: usize>(rows: [[KeyType; COLS]; N]) -> [[KeyType; COLS]; ROWS] {
+    let mut layer = [[KeyType::Instant(Key::Dead); COLS]; ROWS];
+    let mut i = 0;
+    while i < N && i < ROWS {
+        layer[i] = rows[i];
+        i += 1;
+    }
+    layer
+}
+
+/// Parse a hold time written like `200ms` in a [`layout!`]. Panics, failing the build, for
+/// anything else.
+#[doc(hidden)]
+pub const fn parse_ms(s: &str) -> u16 {
+    let bytes = s.as_bytes();
+    let len = bytes.len();
+    if len < 3 || bytes[len - 2] != b'm' || bytes[len - 1] != b's' {
+        panic!("hold times in layout! are written in ms, like 200ms");
+    }
+    let mut ms: u32 = 0;
+    let mut i = 0;
+    while i < len - 2 {
+        let b = bytes[i];
+        if b == b'_' {
+            i += 1;
+            continue;
+        }
+        if !b.is_ascii_digit() {
+            panic!("hold times in layout! are written in ms, like 200ms");
+        }
+        ms = ms * 10 + (b - b'0') as u32;
+        if ms > u16::MAX as u32 {
+            panic!("hold time in layout! is longer than u16::MAX ms");
+        }
+        i += 1;
+    }
+    ms as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use avr_progmem::progmem;
+
+    use crate::combo::ComboRelease;
+    use crate::keycode::k;
+    use crate::layout::{Flavor, Key, KeyType, COLS, ROWS};
+    use crate::macros::{check_layout, parse_ms};
+    use crate::mouse::Mouse;
+
+    progmem! {
+        static progmem MATRIX: [[[KeyType; COLS]; ROWS]; 4] = layout![
+            [
+                HT(ESC, L_CTRL, 200ms) A  SE(AT) LS(K1) MO(1) ___ XXX PT(2) MS(Left) CON(MUTE) SYS(SLEEP) KEY(Key::LayerMo(3)) |
+                TD(COLON LS(COLON), ESC, 250ms) OSM(L_CTRL | L_SHFT) OSL(2) XXX XXX XXX XXX XXX XXX XXX XXX XXX |
+                XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX HT(F, L_SHFT, 200ms, PermissiveHold) |
+                XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX HT(SPACE, MO(1), 1_000ms)
+            ]
+            [ XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX | XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX |
+              XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX | XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX ]
+            [ XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX | XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX |
+              XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX | XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX ]
+            [ XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX | XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX |
+              XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX | XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX XXX ]
+        ];
+    }
+
+    fn key(row: usize, col: usize) -> KeyType {
+        MATRIX.at(0).at(row).at(col).load()
+    }
+
+    #[test]
+    #[should_panic(expected = "layout!: layer 1, row 3 has 11 keys, expected COLS = 12, col 11 is missing")]
+    fn short_row_names_its_layer_and_row() {
+        check_layout(&[&[COLS; ROWS], &[COLS, COLS, COLS, COLS - 1]]);
+    }
+
+    #[test]
+    #[should_panic(expected = "layout!: layer 0, row 1 has 13 keys, expected COLS = 12, col 12 is one too many")]
+    fn long_row_names_its_first_extra_col() {
+        check_layout(&[&[COLS, COLS + 1, COLS, COLS]]);
+    }
+
+    #[test]
+    #[should_panic(expected = "layout!: layer 0 has 3 rows, expected ROWS = 4")]
+    fn missing_row_names_its_layer() {
+        check_layout(&[&[COLS; ROWS - 1]]);
+    }
+
+    #[test]
+    fn hold_times_are_read_as_ms() {
+        assert_eq!(parse_ms("200ms"), 200);
+        assert_eq!(parse_ms("1_000ms"), 1000);
+        assert_eq!(parse_ms("0ms"), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn hold_times_need_a_unit() {
+        parse_ms("200");
+    }
+
+    #[test]
+    fn cells_become_keys() {
+        assert!(matches!(key(0, 0), KeyType::OnHold(Key::KeyCode(k::ESC), 200, Key::KeyCode(k::L_CTRL), None)));
+        assert!(matches!(key(0, 1), KeyType::Instant(Key::KeyCode(k::A))));
+        assert!(matches!(key(0, 2), KeyType::Instant(Key::Modded { code: k::K2, .. })));
+        assert!(matches!(key(0, 3), KeyType::Instant(Key::Modded { mods: 0b0000_0010, code: k::K1 })));
+        assert!(matches!(key(0, 4), KeyType::Instant(Key::LayerMo(1))));
+        assert!(matches!(key(0, 5), KeyType::Instant(Key::Transparent)));
+        assert!(matches!(key(0, 6), KeyType::Instant(Key::Dead)));
+        assert!(matches!(key(0, 7), KeyType::Instant(Key::PassThrough(2))));
+        assert!(matches!(key(0, 8), KeyType::Instant(Key::Mouse(Mouse::Left))));
+        assert!(matches!(key(0, 9), KeyType::Instant(Key::Consumer(_))));
+        assert!(matches!(key(0, 10), KeyType::Instant(Key::System(0x82))));
+        assert!(matches!(key(0, 11), KeyType::Instant(Key::LayerMo(3))));
+        let KeyType::TapDance(dance) = key(1, 0) else { panic!("not a tap dance") };
+        let dance = dance.load();
+        assert_eq!(dance.term, 250);
+        assert!(matches!(dance.tap(1), Key::KeyCode(k::COLON)));
+        assert!(matches!(dance.tap(2), Key::Modded { code: k::COLON, .. }));
+        assert!(matches!(dance.tap(3), Key::Dead));
+        assert!(matches!(dance.hold(1), Key::KeyCode(k::ESC)));
+        assert!(matches!(dance.hold(2), Key::Dead));
+        assert!(matches!(key(1, 1), KeyType::Instant(Key::OneShotMod(0b0000_0011))));
+        assert!(matches!(key(1, 2), KeyType::Instant(Key::OneShotLayer(2))));
+        assert!(matches!(key(2, 11), KeyType::OnHold(Key::KeyCode(k::F), 200, Key::KeyCode(k::L_SHFT), Some(Flavor::PermissiveHold))));
+        assert!(matches!(key(3, 11), KeyType::OnHold(Key::KeyCode(k::SPACE), 1000, Key::LayerMo(1), None)));
+    }
+
+    #[test]
+    fn combos_are_read_with_their_layers_and_release() {
+        let combos = combos![40ms; (0, 1) (0, 2) => ESC, (1, 0) (1, 1) (1, 2) => LS(K1) layers(0, 2) release(All),];
+        assert_eq!(combos.len(), 2);
+        assert_eq!(combos.term(), 40);
+        let combo = combos.load(0);
+        assert_eq!(combo.size(), 2);
+        assert!(combo.contains(1) && combo.contains(2) && !combo.contains(0));
+        assert!(matches!(combo.key, Key::KeyCode(k::ESC)));
+        assert!(combo.layers.is_none() && combo.release == ComboRelease::First);
+        let combo = combos.load(1);
+        assert_eq!(combo.size(), 3);
+        assert!(combo.contains(COLS + 2));
+        assert!(matches!(combo.key, Key::Modded { code: k::K1, .. }));
+        assert!(combo.on_layer(2) && !combo.on_layer(1));
+        assert_eq!(combo.release, ComboRelease::All);
+        assert!(combos![].is_empty());
+        assert_eq!(combos![(0, 1) (0, 2) => ESC].term(), crate::combo::COMBO_TERM_MS);
+    }
+}
@@ -0,0 +1,220 @@
+//! Mouse keys.
+//!
+//! While a movement key is held a [`MouseReport`] is sent every [`MOUSE_INTERVAL_MS`], with the
+//! distance given by the [`Acceleration`] curve for how long the key has been held. Which curve is
+//! used is picked at build time with the `mouse-accel-*` features, see [`ACCELERATION`].
+use crate::report::MouseReport;
+
+/// How often, in ms, a report is sent while the cursor is moving.
+pub const MOUSE_INTERVAL_MS: u16 = 16;
+/// How often, in ms, the wheel scrolls one step while a wheel key is held.
+pub const WHEEL_INTERVAL_MS: u16 = 80;
+
+/// Distance moved per report, in the host's units, with the [`Acceleration::Constant`] curve.
+pub const MOUSE_SPEED: i8 = 6;
+/// Distance moved per report when a key is first pressed with the accelerating curves.
+pub const MOUSE_MIN_SPEED: i8 = 1;
+/// The fastest the accelerating curves will go.
+pub const MOUSE_MAX_SPEED: i8 = 24;
+/// How long, in ms, [`Acceleration::Linear`] takes to go from min to max speed.
+pub const MOUSE_RAMP_MS: u16 = 1000;
+/// How long, in ms, [`Acceleration::Kinetic`] stays at min speed before it starts to accelerate.
+pub const MOUSE_KINETIC_DELAY_MS: u16 = 200;
+
+/// Linear ramp, unless another `mouse-accel-*` feature is enabled.
+#[cfg(not(any(feature = "mouse-accel-constant", feature = "mouse-accel-kinetic")))]
+pub const ACCELERATION: Acceleration = Acceleration::Linear;
+#[cfg(feature = "mouse-accel-constant")]
+pub const ACCELERATION: Acceleration = Acceleration::Constant;
+#[cfg(all(feature = "mouse-accel-kinetic", not(feature = "mouse-accel-constant")))]
+pub const ACCELERATION: Acceleration = Acceleration::Kinetic;
+
+/// What a `Key::Mouse` does while it is held.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Mouse {
+    Up,
+    Down,
+    Left,
+    Right,
+    WheelUp,
+    WheelDown,
+    WheelLeft,
+    WheelRight,
+    /// Mouse button 1 to 5. 1 is the primary button, 2 the secondary and 3 the middle.
+    Button(u8),
+}
+
+/// How the cursor speed depends on how long a movement key has been held.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Acceleration {
+    /// Always [`MOUSE_SPEED`].
+    Constant,
+    /// From [`MOUSE_MIN_SPEED`] to [`MOUSE_MAX_SPEED`] in a straight line over [`MOUSE_RAMP_MS`].
+    Linear,
+    /// [`MOUSE_MIN_SPEED`] for [`MOUSE_KINETIC_DELAY_MS`] for precise positioning, then
+    /// quadratically up to [`MOUSE_MAX_SPEED`] over [`MOUSE_RAMP_MS`]. Like QMK's kinetic mode.
+    Kinetic,
+}
+
+impl Acceleration {
+    /// Distance to move per report for a key that has been held for `held_ms`.
+    pub fn speed(&self, held_ms: u32) -> i8 {
+        let min = MOUSE_MIN_SPEED as u32;
+        let span = (MOUSE_MAX_SPEED - MOUSE_MIN_SPEED) as u32;
+        let ramp = MOUSE_RAMP_MS as u32;
+        match self {
+            Acceleration::Constant => MOUSE_SPEED,
+            Acceleration::Linear => {
+                let t = held_ms.min(ramp);
+                (min + span * t / ramp) as i8
+            }
+            Acceleration::Kinetic => {
+                let t = held_ms.saturating_sub(MOUSE_KINETIC_DELAY_MS as u32).min(ramp);
+                (min + span * t * t / (ramp * ramp)) as i8
+            }
+        }
+    }
+}
+
+/// Turns the held mouse keys into mouse reports.
+pub struct MouseKeys {
+    acceleration: Acceleration,
+    last_buttons: u8,
+    /// When the last report with movement was sent. `None` while no movement key is held.
+    moved_at: Option<u32>,
+    /// When the wheel last scrolled. `None` while no wheel key is held.
+    scrolled_at: Option<u32>,
+}
+
+impl Default for MouseKeys {
+    fn default() -> Self {
+        Self::new(ACCELERATION)
+    }
+}
+
+impl MouseKeys {
+    pub fn new(acceleration: Acceleration) -> Self {
+        Self {
+            acceleration,
+            last_buttons: 0,
+            moved_at: None,
+            scrolled_at: None,
+        }
+    }
+
+    /// The report to send for the held mouse keys, each with how long it has been held, or `None`
+    /// if there is nothing new to tell the host.
+    pub fn update(&mut self, held: &[(Mouse, u32)], now: u32) -> Option<MouseReport> {
+        let mut report = MouseReport::empty();
+        let mut speed = [0i8; 4]; // Up, down, left, right
+        let mut wheel = [false; 4];
+        for (action, held_ms) in held {
+            let s = self.acceleration.speed(*held_ms);
+            match action {
+                Mouse::Up => speed[0] = speed[0].max(s),
+                Mouse::Down => speed[1] = speed[1].max(s),
+                Mouse::Left => speed[2] = speed[2].max(s),
+                Mouse::Right => speed[3] = speed[3].max(s),
+                Mouse::WheelUp => wheel[0] = true,
+                Mouse::WheelDown => wheel[1] = true,
+                Mouse::WheelLeft => wheel[2] = true,
+                Mouse::WheelRight => wheel[3] = true,
+                Mouse::Button(b @ 1..=5) => report.buttons |= 1 << (b - 1),
+                Mouse::Button(_) => {}
+            }
+        }
+
+        let moving = speed.iter().any(|s| *s != 0);
+        if moving && Self::due(self.moved_at, now, MOUSE_INTERVAL_MS) {
+            self.moved_at = Some(now);
+            report.x = speed[3] - speed[2];
+            report.y = speed[1] - speed[0];
+        } else if !moving {
+            self.moved_at = None;
+        }
+
+        let scrolling = wheel.iter().any(|w| *w);
+        if scrolling && Self::due(self.scrolled_at, now, WHEEL_INTERVAL_MS) {
+            self.scrolled_at = Some(now);
+            report.wheel = wheel[0] as i8 - wheel[1] as i8;
+            report.pan = wheel[3] as i8 - wheel[2] as i8;
+        } else if !scrolling {
+            self.scrolled_at = None;
+        }
+
+        // Movement is relative, so a report without any is only needed when the buttons change
+        let has_motion = report.x != 0 || report.y != 0 || report.wheel != 0 || report.pan != 0;
+        if has_motion || report.buttons != self.last_buttons {
+            self.last_buttons = report.buttons;
+            Some(report)
+        } else {
+            None
+        }
+    }
+
+    fn due(last: Option<u32>, now: u32, interval: u16) -> bool {
+        match last {
+            None => true,
+            Some(at) => now.wrapping_sub(at) >= interval as u32,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use super::{Acceleration, Mouse, MouseKeys, MOUSE_INTERVAL_MS, MOUSE_MAX_SPEED, MOUSE_MIN_SPEED, MOUSE_SPEED};
+
+    #[test]
+    fn constant_never_changes() {
+        assert_eq!(Acceleration::Constant.speed(0), MOUSE_SPEED);
+        assert_eq!(Acceleration::Constant.speed(10_000), MOUSE_SPEED);
+    }
+
+    #[test]
+    fn linear_ramps_evenly_from_min_to_max() {
+        let a = Acceleration::Linear;
+        assert_eq!(a.speed(0), MOUSE_MIN_SPEED);
+        assert_eq!(a.speed(500), (MOUSE_MIN_SPEED + MOUSE_MAX_SPEED) / 2);
+        assert_eq!(a.speed(1000), MOUSE_MAX_SPEED);
+        assert_eq!(a.speed(u32::MAX), MOUSE_MAX_SPEED);
+    }
+
+    #[test]
+    fn kinetic_waits_then_accelerates() {
+        let a = Acceleration::Kinetic;
+        assert_eq!(a.speed(0), MOUSE_MIN_SPEED);
+        assert_eq!(a.speed(200), MOUSE_MIN_SPEED);
+        // Slower than linear to begin with, catches up at the end
+        assert!(a.speed(700) < Acceleration::Linear.speed(500));
+        assert!(a.speed(700) > MOUSE_MIN_SPEED);
+        assert_eq!(a.speed(1200), MOUSE_MAX_SPEED);
+    }
+
+    #[test]
+    fn movement_is_sent_every_interval_while_held() {
+        let mut mouse = MouseKeys::new(Acceleration::Constant);
+        let sent: Vec<u32> = (0..50)
+            .filter(|now| mouse.update(&[(Mouse::Right, *now)], *now).is_some())
+            .collect();
+        let interval = MOUSE_INTERVAL_MS as u32;
+        assert_eq!(sent, [0, interval, 2 * interval, 3 * interval]);
+    }
+
+    #[test]
+    fn opposite_directions_cancel_and_diagonals_combine() {
+        let mut mouse = MouseKeys::new(Acceleration::Constant);
+        let report = mouse.update(&[(Mouse::Left, 0), (Mouse::Right, 0), (Mouse::Up, 0)], 0).unwrap();
+        assert_eq!((report.x, report.y), (0, -MOUSE_SPEED));
+    }
+
+    #[test]
+    fn buttons_are_sent_on_change_only() {
+        let mut mouse = MouseKeys::new(Acceleration::Constant);
+        assert_eq!(mouse.update(&[(Mouse::Button(1), 0), (Mouse::Button(3), 0)], 0).unwrap().buttons, 0b101);
+        assert!(mouse.update(&[(Mouse::Button(1), 1), (Mouse::Button(3), 1)], 1).is_none());
+        assert_eq!(mouse.update(&[], 2).unwrap().buttons, 0);
+        assert!(mouse.update(&[], 3).is_none());
+    }
+}
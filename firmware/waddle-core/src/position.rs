@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod position {
     use crate::layout::COLS;
 
@@ -14,11 +15,20 @@ pub mod position {
             Self { row: row as u8, col: col as u8 }
         }
 
-        pub fn row(&self) -> u8 {
+        pub const fn new(row: u8, col: u8) -> Self {
+            Self { row, col }
+        }
+
+        pub const fn row(&self) -> u8 {
             self.row
         }
-        pub fn col(&self) -> u8 {
+        pub const fn col(&self) -> u8 {
             self.col
         }
+
+        /// The index of the button in the matrix, the opposite of `from`.
+        pub const fn index(&self) -> usize {
+            self.row as usize * COLS + self.col as usize
+        }
     }
 }
\ No newline at end of file
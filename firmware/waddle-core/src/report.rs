@@ -0,0 +1,346 @@
+//! The reports sent to the host, and the descriptor for the report protocol interface.
+//!
+//! The keyboard has two HID interfaces. The boot interface only ever sends plain 8 byte
+//! [`KeyboardReport`]s, and only when the host has selected the boot protocol. Everything else
+//! goes out on the report interface, where each kind of report has its own report ID.
+
+/// The protocol selected by the host with SET_PROTOCOL.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Protocol {
+    Boot,
+    Report,
+}
+
+pub const KEYBOARD_REPORT_ID: u8 = 1;
+pub const NKRO_REPORT_ID: u8 = 2;
+pub const CONSUMER_REPORT_ID: u8 = 3;
+pub const SYSTEM_REPORT_ID: u8 = 4;
+pub const MOUSE_REPORT_ID: u8 = 5;
+
+/// The idle rate a keyboard starts out with, until the host changes it with SET_IDLE.
+/// 500 ms is what the HID spec recommends for keyboards.
+pub const DEFAULT_IDLE_MS: u16 = 500;
+
+/// Bytes in the NKRO bitmap. Covers the usages 0x00 to 0xDF, the modifiers have their own byte.
+pub const NKRO_BYTES: usize = 28;
+
+/// How many consumer usages can be held at the same time.
+pub const CONSUMER_USAGES: usize = 4;
+
+/// The largest report, report ID included.
+pub const MAX_REPORT_LEN: usize = 2 + NKRO_BYTES;
+
+/// A boot compatible keyboard report.
+///
+/// This mirrors the layout of `usbd_hid::descriptor::KeyboardReport` without pulling the USB stack
+/// into the core, the binary converts it right before pushing it to the host.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct KeyboardReport {
+    pub modifier: u8,
+    pub keycodes: [u8; 6],
+}
+
+impl KeyboardReport {
+    pub const fn empty() -> Self {
+        Self { modifier: 0, keycodes: [0; 6] }
+    }
+
+    /// The report as sent on the boot interface.
+    pub fn to_boot_bytes(&self) -> [u8; 8] {
+        let mut bytes = [0; 8];
+        bytes[0] = self.modifier;
+        bytes[2..].copy_from_slice(&self.keycodes);
+        bytes
+    }
+}
+
+/// An N-key rollover report, one bit per usage.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct NkroReport {
+    pub modifier: u8,
+    pub keys: [u8; NKRO_BYTES],
+}
+
+impl NkroReport {
+    pub const fn empty() -> Self {
+        Self { modifier: 0, keys: [0; NKRO_BYTES] }
+    }
+
+    pub fn press(&mut self, usage: u8) {
+        if let Some(byte) = self.keys.get_mut(usage as usize / 8) {
+            *byte |= 1 << (usage % 8);
+        }
+    }
+
+    pub fn is_pressed(&self, usage: u8) -> bool {
+        match self.keys.get(usage as usize / 8) {
+            Some(byte) => byte & (1 << (usage % 8)) != 0,
+            None => false,
+        }
+    }
+}
+
+/// Media keys and other usages from the Consumer page.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct ConsumerReport {
+    pub usages: [u16; CONSUMER_USAGES],
+}
+
+impl ConsumerReport {
+    pub const fn empty() -> Self {
+        Self { usages: [0; CONSUMER_USAGES] }
+    }
+}
+
+/// Power, sleep and wake. Only one can be held at a time, 0 when none is.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct SystemReport {
+    pub usage: u8,
+}
+
+impl SystemReport {
+    pub const fn empty() -> Self {
+        Self { usage: 0 }
+    }
+}
+
+/// Mouse buttons and relative movement since the last report.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct MouseReport {
+    /// Button 1 to 5, one bit each.
+    pub buttons: u8,
+    pub x: i8,
+    pub y: i8,
+    pub wheel: i8,
+    /// Horizontal scroll.
+    pub pan: i8,
+}
+
+impl MouseReport {
+    pub const fn empty() -> Self {
+        Self { buttons: 0, x: 0, y: 0, wheel: 0, pan: 0 }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Report {
+    Keyboard(KeyboardReport),
+    Nkro(NkroReport),
+    Consumer(ConsumerReport),
+    System(SystemReport),
+    Mouse(MouseReport),
+}
+
+impl Report {
+    /// A report of the same kind with nothing pressed. Sent when switching to another kind of
+    /// report so the host does not keep the old one's keys held.
+    pub fn released(&self) -> Self {
+        match self {
+            Report::Keyboard(_) => Report::Keyboard(KeyboardReport::empty()),
+            Report::Nkro(_) => Report::Nkro(NkroReport::empty()),
+            Report::Consumer(_) => Report::Consumer(ConsumerReport::empty()),
+            Report::System(_) => Report::System(SystemReport::empty()),
+            Report::Mouse(_) => Report::Mouse(MouseReport::empty()),
+        }
+    }
+
+    /// Whether a keyboard report has the non-modifier usage pressed.
+    pub fn has_key(&self, usage: u8) -> bool {
+        match self {
+            Report::Keyboard(r) => usage != 0 && r.keycodes.contains(&usage),
+            Report::Nkro(r) => r.is_pressed(usage),
+            _ => false,
+        }
+    }
+
+    pub fn is_same_kind(&self, other: &Report) -> bool {
+        core::mem::discriminant(self) == core::mem::discriminant(other)
+    }
+
+    /// Write the report, prefixed with its report ID, as sent on the report interface.
+    /// Returns the number of bytes written.
+    pub fn write(&self, buf: &mut [u8; MAX_REPORT_LEN]) -> usize {
+        match self {
+            Report::Keyboard(r) => {
+                buf[0] = KEYBOARD_REPORT_ID;
+                buf[1..9].copy_from_slice(&r.to_boot_bytes());
+                9
+            }
+            Report::Nkro(r) => {
+                buf[0] = NKRO_REPORT_ID;
+                buf[1] = r.modifier;
+                buf[2..2 + NKRO_BYTES].copy_from_slice(&r.keys);
+                2 + NKRO_BYTES
+            }
+            Report::Consumer(r) => {
+                buf[0] = CONSUMER_REPORT_ID;
+                for (i, usage) in r.usages.iter().enumerate() {
+                    buf[1 + 2 * i..3 + 2 * i].copy_from_slice(&usage.to_le_bytes());
+                }
+                1 + 2 * CONSUMER_USAGES
+            }
+            Report::System(r) => {
+                buf[0] = SYSTEM_REPORT_ID;
+                buf[1] = r.usage;
+                2
+            }
+            Report::Mouse(r) => {
+                buf[0] = MOUSE_REPORT_ID;
+                buf[1] = r.buttons;
+                buf[2] = r.x as u8;
+                buf[3] = r.y as u8;
+                buf[4] = r.wheel as u8;
+                buf[5] = r.pan as u8;
+                6
+            }
+        }
+    }
+}
+
+/// HID report descriptor for the report interface.
+// @formatter:off
+pub const REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01,             // Usage Page (Generic Desktop)
+    0x09, 0x06,             // Usage (Keyboard)
+    0xA1, 0x01,             // Collection (Application)
+    0x85, KEYBOARD_REPORT_ID, //   Report ID
+    0x05, 0x07,             //   Usage Page (Keyboard/Keypad)
+    0x19, 0xE0,             //   Usage Minimum (Left Control)
+    0x29, 0xE7,             //   Usage Maximum (Right GUI)
+    0x15, 0x00,             //   Logical Minimum (0)
+    0x25, 0x01,             //   Logical Maximum (1)
+    0x75, 0x01,             //   Report Size (1)
+    0x95, 0x08,             //   Report Count (8)
+    0x81, 0x02,             //   Input (Data, Variable, Absolute) Modifiers
+    0x75, 0x08,             //   Report Size (8)
+    0x95, 0x01,             //   Report Count (1)
+    0x81, 0x01,             //   Input (Constant) Reserved
+    0x19, 0x00,             //   Usage Minimum (0)
+    0x29, 0xFF,             //   Usage Maximum (255)
+    0x26, 0xFF, 0x00,       //   Logical Maximum (255)
+    0x75, 0x08,             //   Report Size (8)
+    0x95, 0x06,             //   Report Count (6)
+    0x81, 0x00,             //   Input (Data, Array, Absolute) Keys
+    0xC0,                   // End Collection
+
+    0x05, 0x01,             // Usage Page (Generic Desktop)
+    0x09, 0x06,             // Usage (Keyboard)
+    0xA1, 0x01,             // Collection (Application)
+    0x85, NKRO_REPORT_ID,   //   Report ID
+    0x05, 0x07,             //   Usage Page (Keyboard/Keypad)
+    0x19, 0xE0,             //   Usage Minimum (Left Control)
+    0x29, 0xE7,             //   Usage Maximum (Right GUI)
+    0x15, 0x00,             //   Logical Minimum (0)
+    0x25, 0x01,             //   Logical Maximum (1)
+    0x75, 0x01,             //   Report Size (1)
+    0x95, 0x08,             //   Report Count (8)
+    0x81, 0x02,             //   Input (Data, Variable, Absolute) Modifiers
+    0x19, 0x00,             //   Usage Minimum (0)
+    0x29, 0xDF,             //   Usage Maximum (0xDF)
+    0x95, 0xE0,             //   Report Count (224)
+    0x81, 0x02,             //   Input (Data, Variable, Absolute) Key bitmap
+    0xC0,                   // End Collection
+
+    0x05, 0x0C,             // Usage Page (Consumer)
+    0x09, 0x01,             // Usage (Consumer Control)
+    0xA1, 0x01,             // Collection (Application)
+    0x85, CONSUMER_REPORT_ID, //   Report ID
+    0x19, 0x00,             //   Usage Minimum (0)
+    0x2A, 0xFF, 0x03,       //   Usage Maximum (0x3FF)
+    0x15, 0x00,             //   Logical Minimum (0)
+    0x26, 0xFF, 0x03,       //   Logical Maximum (0x3FF)
+    0x75, 0x10,             //   Report Size (16)
+    0x95, CONSUMER_USAGES as u8, //   Report Count
+    0x81, 0x00,             //   Input (Data, Array, Absolute)
+    0xC0,                   // End Collection
+
+    0x05, 0x01,             // Usage Page (Generic Desktop)
+    0x09, 0x80,             // Usage (System Control)
+    0xA1, 0x01,             // Collection (Application)
+    0x85, SYSTEM_REPORT_ID, //   Report ID
+    0x19, 0x81,             //   Usage Minimum (System Power Down)
+    0x29, 0x83,             //   Usage Maximum (System Wake Up)
+    0x15, 0x81,             //   Logical Minimum (0x81)
+    0x26, 0x83, 0x00,       //   Logical Maximum (0x83)
+    0x75, 0x08,             //   Report Size (8)
+    0x95, 0x01,             //   Report Count (1)
+    0x81, 0x40,             //   Input (Data, Array, Absolute, Null State)
+    0xC0,                   // End Collection
+
+    0x05, 0x01,             // Usage Page (Generic Desktop)
+    0x09, 0x02,             // Usage (Mouse)
+    0xA1, 0x01,             // Collection (Application)
+    0x85, MOUSE_REPORT_ID,  //   Report ID
+    0x09, 0x01,             //   Usage (Pointer)
+    0xA1, 0x00,             //   Collection (Physical)
+    0x05, 0x09,             //     Usage Page (Button)
+    0x19, 0x01,             //     Usage Minimum (1)
+    0x29, 0x05,             //     Usage Maximum (5)
+    0x15, 0x00,             //     Logical Minimum (0)
+    0x25, 0x01,             //     Logical Maximum (1)
+    0x75, 0x01,             //     Report Size (1)
+    0x95, 0x05,             //     Report Count (5)
+    0x81, 0x02,             //     Input (Data, Variable, Absolute) Buttons
+    0x75, 0x03,             //     Report Size (3)
+    0x95, 0x01,             //     Report Count (1)
+    0x81, 0x01,             //     Input (Constant) Padding
+    0x05, 0x01,             //     Usage Page (Generic Desktop)
+    0x09, 0x30,             //     Usage (X)
+    0x09, 0x31,             //     Usage (Y)
+    0x09, 0x38,             //     Usage (Wheel)
+    0x15, 0x81,             //     Logical Minimum (-127)
+    0x25, 0x7F,             //     Logical Maximum (127)
+    0x75, 0x08,             //     Report Size (8)
+    0x95, 0x03,             //     Report Count (3)
+    0x81, 0x06,             //     Input (Data, Variable, Relative)
+    0x05, 0x0C,             //     Usage Page (Consumer)
+    0x0A, 0x38, 0x02,       //     Usage (AC Pan)
+    0x95, 0x01,             //     Report Count (1)
+    0x81, 0x06,             //     Input (Data, Variable, Relative)
+    0xC0,                   //   End Collection
+    0xC0,                   // End Collection
+];
+// @formatter:on
+
+#[cfg(test)]
+mod tests {
+    use super::{ConsumerReport, KeyboardReport, MAX_REPORT_LEN, MouseReport, NKRO_BYTES, NkroReport, Report, SystemReport};
+
+    #[test]
+    fn nkro_bitmap_covers_every_non_modifier_usage() {
+        let mut report = NkroReport::empty();
+        for usage in 0..=0xDF {
+            report.press(usage);
+        }
+        assert!(report.keys.iter().all(|b| *b == 0xFF));
+        assert!(report.is_pressed(0xDF));
+        // Modifiers are not in the bitmap
+        report.press(0xE0);
+        assert!(!report.is_pressed(0xE0));
+    }
+
+    #[test]
+    fn reports_are_written_with_their_report_id() {
+        let mut buf = [0; MAX_REPORT_LEN];
+        let keyboard = Report::Keyboard(KeyboardReport { modifier: 2, keycodes: [4, 5, 0, 0, 0, 0] });
+        assert_eq!(keyboard.write(&mut buf), 9);
+        assert_eq!(buf[..9], [1, 2, 0, 4, 5, 0, 0, 0, 0]);
+
+        let mut nkro = NkroReport::empty();
+        nkro.modifier = 1;
+        nkro.press(0x04);
+        assert_eq!(Report::Nkro(nkro).write(&mut buf), 2 + NKRO_BYTES);
+        assert_eq!(buf[..3], [2, 1, 0b10000]);
+
+        let consumer = Report::Consumer(ConsumerReport { usages: [0xE9, 0x192, 0, 0] });
+        assert_eq!(consumer.write(&mut buf), 9);
+        assert_eq!(buf[..9], [3, 0xE9, 0, 0x92, 0x01, 0, 0, 0, 0]);
+
+        assert_eq!(Report::System(SystemReport { usage: 0x82 }).write(&mut buf), 2);
+        assert_eq!(buf[..2], [4, 0x82]);
+
+        let mouse = Report::Mouse(MouseReport { buttons: 1, x: -3, y: 4, wheel: 0, pan: 1 });
+        assert_eq!(mouse.write(&mut buf), 6);
+        assert_eq!(buf[..6], [5, 1, 0xFD, 4, 0, 1]);
+    }
+}
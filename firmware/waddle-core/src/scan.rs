@@ -1,9 +1,16 @@
 use crate::layout::{COLS, ROWS};
 
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub struct Scan {
     pressed: [u16; ROWS],
 }
 
+impl Default for Scan {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Scan {
     pub fn new() -> Self {
         Self {
@@ -12,7 +19,16 @@ impl Scan {
     }
 
     pub fn set_pressed(&mut self, row: &usize, col: &usize) {
-        self.pressed[*row] = self.pressed[*row] | (1 << col)
+        self.pressed[*row] |= 1 << col
+    }
+
+    pub fn set(&mut self, button: &usize, pressed: bool) {
+        let c = button % COLS;
+        let r = button / COLS;
+        match pressed {
+            true => self.pressed[r] |= 1 << c,
+            false => self.pressed[r] &= !(1 << c),
+        }
     }
 
     pub fn is_pressed(&self, button: &usize) -> bool {
@@ -0,0 +1,1710 @@
+use core::cmp::max_by_key;
+use core::ops::Deref;
+
+use heapless::Vec;
+
+use crate::{rvec, vec};
+use crate::combo::{Combo, ComboRelease};
+use crate::layers::Layers;
+use crate::layout::{BUTTONS, Flavor, Key, KeyType, LAYER_LEDS, Layout, LAYOUT, LEDS, TAP_DANCE_TAPS, TapDance};
+use crate::mouse::Mouse;
+use crate::position::position::Position;
+use crate::scan::Scan;
+use crate::state::ButtonState::{Held, JustReleased, Pressed, Released};
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+pub enum ButtonState {
+    Held,
+    JustReleased,
+    Pressed,
+    Released,
+}
+
+/// How long, in ms, a released key stays `JustReleased`.
+/// Long enough for a tapped OnHold to make it into a report before it is blanked.
+pub const JUST_RELEASED_MS: u16 = 5;
+/// How long, in ms, a `LayerTapToggle` can be held, and released between taps, to count as a tap.
+pub const TAPPING_TERM_MS: u16 = 200;
+/// How many taps in a row a `LayerTapToggle` needs to toggle its layer.
+pub const TAP_TOGGLE_TAPS: u8 = 5;
+/// How long, in ms, a tapped `OneShotMod` or `OneShotLayer` waits for the next key.
+pub const ONE_SHOT_TIMEOUT_MS: u16 = 3000;
+/// How many `OneShotMod` and `OneShotLayer` keys can be held at once.
+const ONE_SHOT_KEYS: usize = 4;
+
+/// The longest time, in ms, a button is seen to be held or released for. Its timestamps are moved
+/// up on every tick once it is past this, so the truncated timestamps never wrap and anything
+/// longer reads as this.
+const MAX_AGE_MS: u16 = 60_000;
+
+/// Truncated timestamps, in ms from the clock given to `State::tick`, of the last press and
+/// release. Like in `debounce`, they only take two bytes each for every button.
+#[derive(Copy, Clone, Eq, PartialEq)]
+struct Time {
+    pressed: u16,
+    released: u16,
+}
+
+impl Time {
+    fn new() -> Self { Self { pressed: 0, released: 0 } }
+}
+
+/// Whether what this press does to the layers, one-shots and functions has been done, so it is
+/// only done once.
+const ACTED: u8 = 1 << 0;
+/// Pressed while an `OnHold` was undecided, or for a combo, so it waits in `State::held_back` to
+/// be sent.
+const HELD_BACK: u8 = 1 << 1;
+/// Sent for one tick though it was already released, after waiting in `State::held_back` or when
+/// a tap dance ends after its last release.
+const TAPPED: u8 = 1 << 2;
+/// Pressed as part of a combo that fired, so it sends nothing of its own until released.
+const IN_COMBO: u8 = 1 << 3;
+
+/// What an `OnHold` turned out to be for one press.
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Decision {
+    Tap,
+    Hold,
+}
+
+/// Kept small, there is one for every button in the 2.5 KiB of SRAM.
+struct Button {
+    state: ButtonState,
+    time: Time,
+    /// Tap or hold, once an `OnHold` has picked one for this press.
+    decision: Option<Decision>,
+    /// Presses in the tap dance so far, counting the current one.
+    taps: u8,
+    /// `ACTED`, `HELD_BACK`, `TAPPED` and `IN_COMBO`.
+    flags: u8,
+}
+
+impl Button {
+    // Decided as a hold so nothing is tapped before the first press
+    fn new() -> Self {
+        Self {
+            state: Released,
+            time: Time::new(),
+            decision: Some(Decision::Hold),
+            taps: 0,
+            flags: 0,
+        }
+    }
+
+    fn has(&self, flag: u8) -> bool {
+        self.flags & flag != 0
+    }
+
+    fn set(&mut self, flag: u8, on: bool) {
+        match on {
+            true => self.flags |= flag,
+            false => self.flags &= !flag,
+        }
+    }
+
+    fn released(&mut self, now: u32) {
+        if self.state == Held {
+            self.time.released = now as u16;
+        }
+        self.state = Released;
+        self.set(IN_COMBO, false);
+    }
+    /// Whether this is a new press.
+    fn pressed(&mut self, now: u32) -> bool {
+        let new = self.state == Released;
+        if new {
+            // A tap dance goes on until it is decided
+            self.taps = match self.decision {
+                None if (self.taps as usize) < TAP_DANCE_TAPS => self.taps + 1,
+                _ => 1,
+            };
+            self.time.pressed = now as u16;
+            self.set(ACTED, false);
+            self.decision = None;
+        }
+        self.state = Held;
+        new
+    }
+
+    fn held_for(&self, now: u32) -> u32 {
+        (now as u16).wrapping_sub(self.time.pressed) as u32
+    }
+
+    fn released_for(&self, now: u32) -> u32 {
+        (now as u16).wrapping_sub(self.time.released) as u32
+    }
+
+    /// How long the key was held the last time it was pressed.
+    fn last_held_for(&self) -> u32 {
+        self.time.released.wrapping_sub(self.time.pressed) as u32
+    }
+
+    /// Move the timestamps up to at most `MAX_AGE_MS` ago. Once released both move together, so
+    /// how long it was held stays the same.
+    fn age(&mut self, now: u32) {
+        let now = now as u16;
+        match self.state {
+            Held => {
+                if now.wrapping_sub(self.time.pressed) > MAX_AGE_MS {
+                    self.time.pressed = now.wrapping_sub(MAX_AGE_MS);
+                }
+                if now.wrapping_sub(self.time.released) > MAX_AGE_MS {
+                    self.time.released = now.wrapping_sub(MAX_AGE_MS);
+                }
+            }
+            _ => {
+                let over = now.wrapping_sub(self.time.released).saturating_sub(MAX_AGE_MS);
+                self.time.released = self.time.released.wrapping_add(over);
+                self.time.pressed = self.time.pressed.wrapping_add(over);
+            }
+        }
+    }
+
+    fn is_pressed(&self) -> bool {
+        self.state == Held
+    }
+}
+
+/// What the buttons at the front of `State::held_back` are waiting for.
+enum Chord {
+    /// A combo they may still be part of.
+    Waiting,
+    /// The combo with this index, and how many of them it takes.
+    Combo(usize, usize),
+    /// Nothing, they go out as themselves.
+    None,
+}
+
+/// Taps counted towards toggling the layer of a `LayerTapToggle`.
+#[derive(Copy, Clone)]
+struct TapToggle {
+    button: usize,
+    layer: u8,
+    taps: u8,
+    /// Whether the release of the current press has been counted, so it is only counted once.
+    counted: bool,
+}
+
+/// Modifiers and layers from tapped `OneShotMod` and `OneShotLayer` keys.
+#[derive(Copy, Clone)]
+struct OneShot {
+    mods: u8,
+    layers: Layers,
+}
+
+impl OneShot {
+    fn none() -> Self { Self { mods: 0, layers: Layers::empty() } }
+
+    fn is_none(&self) -> bool {
+        self.mods == 0 && self.layers.is_empty()
+    }
+}
+
+/// Where the one-shot keys are at.
+struct OneShots {
+    /// The one-shot keys being held, with the button they are on, to tell once they are released
+    /// whether they were tapped.
+    held: Vec<(usize, Key), ONE_SHOT_KEYS>,
+    /// Tapped, waiting for the next key.
+    armed: OneShot,
+    /// When `armed` was last tapped, for `ONE_SHOT_TIMEOUT_MS`.
+    armed_at: u32,
+    /// The button that was pressed next and what it got, until its key is no longer sent.
+    applied: Option<(usize, OneShot)>,
+    /// Modifiers locked on by tapping their key twice. Layers are locked in `State::locked_layers`.
+    locked_mods: u8,
+}
+
+impl OneShots {
+    fn new() -> Self {
+        Self { held: Vec::new(), armed: OneShot::none(), armed_at: 0, applied: None, locked_mods: 0 }
+    }
+}
+
+pub struct State {
+    layout: &'static Layout,
+    keys: Vec<Button, BUTTONS>,
+    leds: u8,
+    nkro: bool,
+    now: u32,
+    /// Layers kept on by `LayerToggle`, `LayerTo` and `LayerTapToggle`.
+    locked_layers: Layers,
+    /// The active layers as of the end of the last tick, see `find_active_layers`.
+    active: Layers,
+    default_layer: u8,
+    tap_toggle: Option<TapToggle>,
+    /// Buttons pressed while an `OnHold` was undecided, or that may be part of a combo, in the
+    /// order they were pressed.
+    held_back: Vec<usize, BUTTONS>,
+    /// The combos whose key is held, by index. Each takes at least two buttons.
+    combos: Vec<usize, { BUTTONS / 2 }>,
+    one_shots: OneShots,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl State {
+    pub fn new() -> Self {
+        Self::with_layout(&LAYOUT)
+    }
+
+    pub fn with_layout(layout: &'static Layout) -> Self {
+        let mut state = Self {
+            layout,
+            keys: rvec![Button::new(), BUTTONS],
+            leds: 0,
+            nkro: true,
+            now: 0,
+            locked_layers: Layers::empty(),
+            active: Layers::of(&[0]),
+            default_layer: 0,
+            tap_toggle: None,
+            held_back: Vec::new(),
+            combos: Vec::new(),
+            one_shots: OneShots::new(),
+        };
+        state.active = state.find_active_layers();
+        state
+    }
+
+    /// Update the buttons with a new, debounced, scan. `now` is a monotonic timestamp in ms.
+    pub fn tick(&mut self, scan: &Scan, now: u32) -> [ButtonState; BUTTONS] {
+        // The key is either pressed or the key is released.
+        // We want to know for how long the key has been in each state since the last change.
+        // The pressed_time will help us know when to activate for example OnHolds
+        // The released_time is how a TapDance knows whether it is tapped again in time.
+        self.now = now;
+        // Nothing goes past a held back button, so the order they were pressed in is kept
+        // The layers the last tick left until the scan is in. They are worked out once for acting
+        // on it and once more for the keys it ends up with, every step in between is handed them
+        let active = self.active;
+        let pending = self.hold_tap_pending(&active) || !self.held_back.is_empty();
+        let layer = highest_layer(&active);
+        for i in 0..BUTTONS {
+            self.keys[i].age(now);
+            match scan.is_pressed(&i) {
+                true => if self.keys[i].pressed(now) {
+                    // Looked up from the layer it is pressed on, which it may turn off again
+                    if let KeyType::Instant(key @ (Key::OneShotMod(_) | Key::OneShotLayer(_))) = self.key_type(&Position::from(i), layer, &active) {
+                        let _ = self.one_shots.held.push((i, key));
+                    }
+                    if !self.keys[i].has(HELD_BACK) && (pending || self.in_a_combo(i, layer)) {
+                        self.keys[i].set(HELD_BACK, true);
+                        let _ = self.held_back.push(i);
+                    }
+                },
+                false => self.keys[i].released(now),
+            }
+        }
+        self.end_combos();
+        let active = self.find_active_layers();
+        self.replay_held_back(&active);
+        self.decide_hold_taps(&active);
+        self.count_tap_toggle();
+        self.update_one_shots(&active);
+        self.apply_layer_actions(&active);
+        self.active = self.find_active_layers();
+
+        // We return a simple state of the keys instead of the actual keys due to space limitations.
+        // A Key can be *BIG* space wise. The more types we add the more memory it could potentially
+        // occupy. This is problematic on low-memory devices like the atmega32u4.
+        // So instead of sending back the keys for `Keyboard` to store we send back a simple array
+        // with enums. This takes much less space as we don't add things to these enums. They can
+        // be stored as simple numbers by rust (or some other more space efficient way)
+        let active = self.active;
+        let layer = highest_layer(&active);
+        let mut button_state = [Released; BUTTONS];
+        button_state.iter_mut().enumerate()
+            .for_each(|(i, bs)| {
+                let k = &self.keys[i];
+                *bs = match k.is_pressed() {
+                    _ if k.has(TAPPED) => Held,
+                    true if k.has(HELD_BACK) => Pressed,
+                    true if k.has(IN_COMBO) => Held,
+                    true => {
+                        let key_type = self.key_type(&Position::from(i), layer, &active);
+                        match key_type {
+                            KeyType::Instant(_) => Held,
+                            KeyType::OnHold(..) => match k.decision {
+                                Some(Decision::Hold) => Held,
+                                _ => Pressed,
+                            },
+                            KeyType::TapDance(_) => match k.decision {
+                                Some(_) => Held,
+                                None => Pressed,
+                            },
+                        }
+                    }
+                    false => match k.released_for(now) < JUST_RELEASED_MS as u32 {
+                        true => JustReleased,
+                        false => Released,
+                    }
+                }
+            });
+        button_state
+    }
+
+    pub fn keys(&self) -> Vec<Key, BUTTONS> {
+        // // 1. Find which layer we are on
+        // // 2. Get all keys on that layer, or lower if current is PassThrough
+        // 3. Add KeyCodes and Functions as events
+        // 4. Check if on-holds. If they are above limit, get the key.
+        //      If they are below, ignore.
+
+        let active = self.active;
+        let layer = highest_layer(&active);
+        // A key is relevant if it is pressed or if it is being held.
+        // This is true for Instants.
+        // For OnHold a key is relevant if it is held OR just released.
+        // If a key is NOT OnHold, then we should register release as release of the button, simple
+        // If it IS OnHold, the key is relevant and we need to check
+        //      1) Is the key still being held?
+        //          1.1) If decided to be a hold then we send key2
+        //          1.2) If undecided we do nothing
+        //      2) Is the key released?
+        //          2.1) If released for JUST_RELEASED_MS or more it's dead. Blank report.
+        //          2.2) If released for less than JUST_RELEASED_MS check the decision.
+        //              2.2.1) If it was a hold it's dead. Blank report.
+        //              2.2.2) If it was a tap send key1. A later tick will blank it.
+        // See `decide_hold_taps` for how the decision is made.
+
+        // The buttons of a combo send nothing, so there is room for its key
+        let keys: Vec<Key, BUTTONS> = self.keys.iter().enumerate()
+            .map(|(i, button)| (Position::from(i), button))
+            .filter_map(|(p, button)| self.get_key(&p, layer, &active, button))
+            .chain(self.combo_keys().map(|(key, _)| key))
+            .collect();
+        keys
+    }
+
+    /// The held mouse keys, with how long, in ms, each has been held.
+    pub fn mouse_keys(&self) -> Vec<(Mouse, u32), BUTTONS> {
+        let active = self.active;
+        let layer = highest_layer(&active);
+        self.keys.iter().enumerate()
+            .filter(|(_, button)| button.is_pressed())
+            .filter_map(|(i, button)| match self.get_key(&Position::from(i), layer, &active, button) {
+                Some(Key::Mouse(m)) => Some((m, button.held_for(self.now))),
+                _ => None,
+            })
+            .chain(self.combo_keys().filter_map(|(key, held_for)| match key {
+                Key::Mouse(m) => Some((m, held_for)),
+                _ => None,
+            }))
+            .collect()
+    }
+
+    /// The keys of the combos that are held, with how long, in ms, each has been held.
+    fn combo_keys(&self) -> impl Iterator<Item = (Key, u32)> + '_ {
+        let combos = self.layout.combos();
+        self.combos.iter().map(move |&c| {
+            let combo = combos.load(c);
+            let held_for = combo.buttons.iter().flatten()
+                .map(|p| self.keys[p.index()].held_for(self.now))
+                .min()
+                .unwrap_or(0);
+            (combo.key, held_for)
+        })
+    }
+
+    /// The active layers, as the last tick left them.
+    pub fn active_layers(&self) -> Layers {
+        self.active
+    }
+
+    /// The active layers. The default layer and the locked layers are always active, the others
+    /// while a `LayerMo` for them is held on a layer below, or through the tri layer. Only pressed
+    /// and tapped buttons can hold a layer on, the released ones aren't looked up.
+    fn find_active_layers(&self) -> Layers {
+        let mut active = self.locked_layers;
+        active.insert(self.default_layer);
+        for (key, _) in self.combo_keys() {
+            if let Key::LayerMo(l) | Key::LayerTapToggle(l) | Key::OneShotLayer(l) = key {
+                active.insert(l);
+            }
+        }
+        active = active.union(self.one_shots.armed.layers);
+        if let Some((_, applied)) = self.one_shots.applied {
+            active = active.union(applied.layers);
+        }
+        // Going up from the base layer means a layer key only has to be on the layer it is
+        // pressed from, what the layers it turns on have in that position doesn't matter
+        for layer in (0..self.layout.layers()).map(|l| l as u8) {
+            if !active.contains(layer) {
+                continue;
+            }
+            active = self.keys.iter().enumerate()
+                .filter(|(_, button)| button.is_pressed() || button.has(TAPPED))
+                .filter_map(|(i, button)| self.get_key(&Position::from(i), layer, &active, button))
+                .fold(active, |mut active, k| {
+                    if let Key::LayerMo(l) | Key::LayerTapToggle(l) | Key::OneShotLayer(l) = k {
+                        active.insert(l);
+                    }
+                    active
+                });
+            if let Some(tri_layer) = self.layout.tri_layer() {
+                active = tri_layer.apply(active);
+            }
+        }
+        active
+    }
+
+    /// Whether an `OnHold` or `TapDance` that isn't held back is yet to decide between tap and hold. Until the
+    /// tick after it has, the keys pressed after it are held back.
+    fn hold_tap_pending(&self, active: &Layers) -> bool {
+        let layer = highest_layer(active);
+        self.keys.iter().enumerate()
+            .filter(|(_, button)| button.decision.is_none() && !button.has(HELD_BACK))
+            .any(|(i, _)| matches!(self.key_type(&Position::from(i), layer, active), KeyType::OnHold(..) | KeyType::TapDance(_)))
+    }
+
+    /// Send the next held back button, once no `OnHold` or `TapDance` is pending and it can't be
+    /// part of a combo, or fire the combo it is part of. One per tick, so every one goes out in its
+    /// own report and the host sees them in the order they were pressed. One that has been
+    /// released already is sent for a single tick.
+    fn replay_held_back(&mut self, active: &Layers) {
+        self.keys.iter_mut().for_each(|button| button.set(TAPPED, false));
+        if self.held_back.is_empty() || self.hold_tap_pending(active) {
+            return;
+        }
+        match self.chord(highest_layer(active)) {
+            Chord::Waiting => {}
+            Chord::Combo(combo, buttons) => self.fire_combo(combo, buttons),
+            Chord::None => {
+                let button = &mut self.keys[self.held_back.remove(0)];
+                button.set(HELD_BACK, false);
+                button.set(TAPPED, !button.is_pressed());
+            }
+        }
+    }
+
+    /// Whether the button at index `i` is in a combo that works on `layer`.
+    fn in_a_combo(&self, i: usize, layer: u8) -> bool {
+        let combos = self.layout.combos();
+        (0..combos.len())
+            .map(|c| combos.load(c))
+            .any(|combo| combo.on_layer(layer) && combo.contains(i))
+    }
+
+    /// Match the buttons at the front of `held_back` against the combos. The ones that are still
+    /// held and were pressed within the combo term of the first are pressed together. Of the
+    /// combos they start with, the one with the most buttons wins, unless a bigger one can still
+    /// be finished: the term isn't over and nothing else has been pressed or released.
+    fn chord(&self, layer: u8) -> Chord {
+        let combos = self.layout.combos();
+        let Some(&first) = self.held_back.first() else { return Chord::None };
+        let term = combos.term() as u32;
+        let start = self.keys[first].time.pressed;
+        let together = self.held_back.iter()
+            .take_while(|&&i| self.keys[i].is_pressed() && self.keys[i].time.pressed.wrapping_sub(start) as u32 <= term)
+            .count();
+        let together = &self.held_back[..together];
+        let can_wait = together.len() == self.held_back.len() && self.keys[first].held_for(self.now) <= term;
+        let mut best: Option<(usize, usize)> = None;
+        for c in 0..combos.len() {
+            let combo = combos.load(c);
+            if !combo.on_layer(layer) {
+                continue;
+            }
+            let len = combo.size();
+            if len > together.len() {
+                if can_wait && together.iter().all(|&i| combo.contains(i)) {
+                    return Chord::Waiting;
+                }
+            } else if together[..len].iter().all(|&i| combo.contains(i)) && best.is_none_or(|(_, n)| len > n) {
+                best = Some((c, len));
+            }
+        }
+        match best {
+            Some((combo, buttons)) => Chord::Combo(combo, buttons),
+            None => Chord::None,
+        }
+    }
+
+    /// Take the first `buttons` held back buttons for the combo with index `combo` and hold its
+    /// key. Layer keys act right away, as they would in `apply_layer_actions`, and other keys get
+    /// the one-shots, which last while the first button is held.
+    fn fire_combo(&mut self, combo: usize, buttons: usize) {
+        let first = self.held_back[0];
+        for _ in 0..buttons {
+            let button = &mut self.keys[self.held_back.remove(0)];
+            button.set(HELD_BACK, false);
+            button.set(IN_COMBO, true);
+            // Nothing to tap once it is released
+            button.decision = Some(Decision::Hold);
+        }
+        let _ = self.combos.push(combo);
+        match self.layout.combos().load(combo).key {
+            Key::LayerToggle(l) => self.locked_layers.toggle(l),
+            Key::LayerTo(l) => self.locked_layers = Layers::of(&[l]),
+            Key::DefaultLayer(l) => self.default_layer = l,
+            Key::LayerMo(_) | Key::LayerTapToggle(_) | Key::OneShotMod(_) | Key::OneShotLayer(_) => {}
+            Key::Function(f) => f(self),
+            _ => self.apply_one_shot(first),
+        }
+    }
+
+    /// Let go of the combos whose buttons were released, the first one or all of them depending
+    /// on the combo.
+    fn end_combos(&mut self) {
+        let combos = self.layout.combos();
+        let keys = &self.keys;
+        self.combos.retain(|&c| {
+            let combo: Combo = combos.load(c);
+            let mut held = combo.buttons.iter().flatten().map(|p| keys[p.index()].has(IN_COMBO));
+            match combo.release {
+                ComboRelease::First => held.all(|h| h),
+                ComboRelease::All => held.any(|h| h),
+            }
+        });
+    }
+
+    /// Decide between tap and hold for the `OnHold` keys that haven't yet. Held past the wait time
+    /// is a hold and released before it is a tap, unless the flavor made it a hold already because
+    /// of the keys pressed after it.
+    fn decide_hold_taps(&mut self, active: &Layers) {
+        let layer = highest_layer(active);
+        for i in 0..BUTTONS {
+            if self.keys[i].decision.is_some() {
+                continue;
+            }
+            match self.key_type(&Position::from(i), layer, active) {
+                KeyType::OnHold(_, hold_limit, _, flavor) => {
+                    let flavor = flavor.unwrap_or(self.layout.flavor());
+                    self.keys[i].decision = self.decide(i, hold_limit, flavor);
+                }
+                KeyType::TapDance(dance) => {
+                    let decision = self.decide_dance(i, &dance.load());
+                    let button = &mut self.keys[i];
+                    button.decision = decision;
+                    if decision.is_some() && !button.is_pressed() {
+                        button.set(TAPPED, true);
+                    }
+                }
+                KeyType::Instant(_) => {}
+            }
+        }
+    }
+
+    /// A tap dance is decided once it is held past its term, or when it can't go on: another key
+    /// is pressed, it isn't tapped again within the term, or there is nothing for another tap.
+    fn decide_dance(&self, i: usize, dance: &TapDance) -> Option<Decision> {
+        let button = &self.keys[i];
+        let held_for = button.held_for(self.now);
+        let interrupted = self.keys.iter().enumerate()
+            .any(|(j, other)| j != i && other.held_for(self.now) < held_for);
+        if button.is_pressed() {
+            if held_for > dance.term as u32 {
+                return match dance.hold(button.taps) {
+                    Key::Dead => Some(Decision::Tap),
+                    _ => Some(Decision::Hold),
+                };
+            }
+            return match interrupted {
+                true => Some(Decision::Tap),
+                false => None,
+            };
+        }
+        let last = matches!((dance.tap(button.taps + 1), dance.hold(button.taps + 1)), (Key::Dead, Key::Dead));
+        match interrupted || last || button.released_for(self.now) > dance.term as u32 {
+            true => Some(Decision::Tap),
+            false => None,
+        }
+    }
+
+    fn decide(&self, i: usize, hold_limit: u16, flavor: Flavor) -> Option<Decision> {
+        let button = &self.keys[i];
+        if !button.is_pressed() {
+            return match button.last_held_for() > hold_limit as u32 {
+                true => Some(Decision::Hold),
+                false => Some(Decision::Tap),
+            };
+        }
+        let held_for = button.held_for(self.now);
+        if held_for > hold_limit as u32 {
+            return Some(Decision::Hold);
+        }
+        // Keys pressed after this one, and still held or released since
+        let mut later = self.keys.iter().enumerate()
+            .filter(|(j, other)| *j != i && other.held_for(self.now) < held_for)
+            .map(|(_, other)| other);
+        let hold = match flavor {
+            Flavor::TapPreferred => false,
+            Flavor::HoldOnOtherKeyPress => later.next().is_some(),
+            Flavor::PermissiveHold => later.any(|other| !other.is_pressed()),
+        };
+        match hold {
+            true => Some(Decision::Hold),
+            false => None,
+        }
+    }
+
+    /// Do what the layer keys that were pressed since the last tick do, and give the one-shot
+    /// modifiers and layers to the first other key. They only act once per press, even if the
+    /// layer they are on is switched away from while they are held.
+    fn apply_layer_actions(&mut self, active: &Layers) {
+        let layer = highest_layer(active);
+        for i in 0..BUTTONS {
+            let button = &self.keys[i];
+            if button.has(ACTED) {
+                continue;
+            }
+            let key = match self.get_key(&Position::from(i), layer, active, button) {
+                Some(key) => key,
+                None => continue,
+            };
+            match key {
+                // Whatever is above a one-shot key on the layer it turns on is not another key
+                _ if self.one_shots.held.iter().any(|(b, _)| *b == i) => {}
+                Key::LayerToggle(l) => self.locked_layers.toggle(l),
+                Key::LayerTo(l) => self.locked_layers = Layers::of(&[l]),
+                Key::DefaultLayer(l) => self.default_layer = l,
+                Key::LayerTapToggle(l) => self.start_tap_toggle(i, l),
+                Key::LayerMo(_) => {}
+                Key::Function(f) => f(self),
+                _ => self.apply_one_shot(i),
+            }
+            self.keys[i].set(ACTED, true);
+        }
+    }
+
+    /// Give what the one-shot keys were tapped for to the button at index `i`.
+    fn apply_one_shot(&mut self, i: usize) {
+        let one_shots = &mut self.one_shots;
+        if !one_shots.armed.is_none() {
+            one_shots.applied = Some((i, one_shots.armed));
+            one_shots.armed = OneShot::none();
+        }
+    }
+
+    /// Arm or lock the one-shot keys that were tapped since the last tick, let go of what was
+    /// applied once the key it went to is no longer sent, and drop what was armed for too long.
+    fn update_one_shots(&mut self, active: &Layers) {
+        let mut i = 0;
+        while i < self.one_shots.held.len() {
+            let (b, key) = self.one_shots.held[i];
+            let button = &self.keys[b];
+            if button.is_pressed() {
+                i += 1;
+                continue;
+            }
+            self.one_shots.held.remove(i);
+            let held_for = button.held_for(self.now);
+            let interrupted = self.keys.iter().enumerate()
+                .any(|(j, other)| j != b && other.held_for(self.now) < held_for);
+            if !interrupted && button.last_held_for() < TAPPING_TERM_MS as u32 {
+                self.tap_one_shot(key);
+            }
+        }
+
+        if let Some((b, _)) = self.one_shots.applied {
+            let button = &self.keys[b];
+            if !button.has(IN_COMBO) && self.get_key(&Position::from(b), highest_layer(active), active, button).is_none() {
+                self.one_shots.applied = None;
+            }
+        }
+        if self.now.wrapping_sub(self.one_shots.armed_at) > ONE_SHOT_TIMEOUT_MS as u32 {
+            self.one_shots.armed = OneShot::none();
+        }
+    }
+
+    /// A one-shot key was tapped. Tapped again before the next key it locks, and a locked one is
+    /// unlocked by tapping it.
+    fn tap_one_shot(&mut self, key: Key) {
+        let one_shots = &mut self.one_shots;
+        match key {
+            Key::OneShotMod(mods) if one_shots.locked_mods & mods == mods => one_shots.locked_mods &= !mods,
+            Key::OneShotMod(mods) if one_shots.armed.mods & mods == mods => {
+                one_shots.armed.mods &= !mods;
+                one_shots.locked_mods |= mods;
+            }
+            Key::OneShotMod(mods) => {
+                one_shots.armed.mods |= mods;
+                one_shots.armed_at = self.now;
+            }
+            Key::OneShotLayer(l) if self.locked_layers.contains(l) => self.locked_layers.toggle(l),
+            Key::OneShotLayer(l) if one_shots.armed.layers.contains(l) => {
+                one_shots.armed.layers.toggle(l);
+                self.locked_layers.insert(l);
+            }
+            Key::OneShotLayer(l) => {
+                one_shots.armed.layers.insert(l);
+                one_shots.armed_at = self.now;
+            }
+            _ => {}
+        }
+    }
+
+    /// The modifiers of the one-shot keys, for the report: the locked ones, and the tapped ones
+    /// once the next key is pressed, for as long as that key is sent. Held one-shot keys are
+    /// among the `keys`.
+    pub fn one_shot_mods(&self) -> u8 {
+        let applied = match self.one_shots.applied {
+            Some((_, applied)) => applied.mods,
+            None => 0,
+        };
+        self.one_shots.locked_mods | applied
+    }
+
+    /// A `LayerTapToggle` was pressed. Keep counting if it is the same key as last time and it
+    /// was let go of recently enough, otherwise start over.
+    fn start_tap_toggle(&mut self, button: usize, layer: u8) {
+        let time = self.keys[button].time;
+        let again = time.pressed.wrapping_sub(time.released) <= TAPPING_TERM_MS;
+        self.tap_toggle = match self.tap_toggle {
+            Some(t) if t.button == button && t.layer == layer && again => Some(TapToggle { counted: false, ..t }),
+            _ => Some(TapToggle { button, layer, taps: 0, counted: false }),
+        };
+    }
+
+    /// Count the release of the `LayerTapToggle` being tapped, if it was short enough to be a tap.
+    fn count_tap_toggle(&mut self) {
+        let Some(mut t) = self.tap_toggle else { return };
+        let button = &self.keys[t.button];
+        if button.is_pressed() || t.counted {
+            return;
+        }
+        t.counted = true;
+        t.taps = match button.last_held_for() < TAPPING_TERM_MS as u32 {
+            true => t.taps + 1,
+            false => 0,
+        };
+        if t.taps >= TAP_TOGGLE_TAPS {
+            self.locked_layers.toggle(t.layer);
+            t.taps = 0;
+        }
+        self.tap_toggle = Some(t);
+    }
+
+    /// The layer that is active when nothing is held. Set by `DefaultLayer` keys.
+    pub fn default_layer(&self) -> u8 {
+        self.default_layer
+    }
+
+    /// Restore a default layer, such as the one saved the last time it changed.
+    pub fn set_default_layer(&mut self, layer: u8) {
+        if (layer as usize) < self.layout.layers() {
+            self.default_layer = layer;
+            self.active = self.find_active_layers();
+        }
+    }
+
+    /// The key to act on at `position` as seen from `layer`, following `Transparent` and
+    /// `PassThrough` down. A loop rather than recursion, there can be up to 256 layers.
+    fn get_key(&self, position: &Position, mut layer: u8, active: &Layers, button: &Button) -> Option<Key> {
+        if button.has(HELD_BACK) || button.has(IN_COMBO) {
+            return None;
+        }
+        loop {
+            let key = match self.layout.get_key(layer, position) {
+                KeyType::Instant(key) if button.is_pressed() || button.has(TAPPED) => key,
+                KeyType::Instant(_) => return None,
+                KeyType::OnHold(key1, _, key2, _) => self.get_hold_key(key1, key2, button)?,
+                KeyType::TapDance(dance) => self.get_dance_key(&dance.load(), button)?,
+            };
+            match key {
+                Key::Transparent | Key::PassThrough(_) => layer = self.fall_through(key, layer, active)?,
+                Key::Dead => return None,
+                key => return Some(key),
+            }
+        }
+    }
+
+    /// The key at `position` as seen from `layer`, following `Transparent` and `PassThrough` down.
+    fn key_type(&self, position: &Position, mut layer: u8, active: &Layers) -> KeyType {
+        loop {
+            match self.layout.get_key(layer, position) {
+                KeyType::Instant(key @ (Key::Transparent | Key::PassThrough(_))) => match self.fall_through(key, layer, active) {
+                    Some(l) => layer = l,
+                    None => return KeyType::Instant(Key::Dead),
+                },
+                key_type => return key_type,
+            }
+        }
+    }
+
+    /// The layer a `Transparent` or `PassThrough` on `layer` takes its key from. Always a lower
+    /// one, so following them down ends. `None` where there is nothing below, it is `Dead`.
+    fn fall_through(&self, key: Key, layer: u8, active: &Layers) -> Option<u8> {
+        match key {
+            Key::Transparent => below(layer, active, self.default_layer),
+            Key::PassThrough(go_down) => layer.checked_sub(go_down).filter(|&l| l < layer),
+            _ => None,
+        }
+    }
+
+    fn get_hold_key(&self, key1: Key, key2: Key, button: &Button) -> Option<Key> {
+        // // If the key is pressed, but not decided to be a hold yet, then send no key.
+        // // If the key is pressed and decided to be a hold send key2
+        // // If the key was just released and decided to be a tap send key1
+        match (button.state, button.decision) {
+            (Released, Some(Decision::Tap)) if button.has(TAPPED) => Some(key1),
+            (Released, Some(Decision::Tap)) => match button.released_for(self.now) < JUST_RELEASED_MS as u32 {
+                true => Some(key1),
+                false => None,
+            },
+            (Held, Some(Decision::Hold)) => Some(key2),
+            _ => None
+        }
+    }
+
+
+    fn get_dance_key(&self, dance: &TapDance, button: &Button) -> Option<Key> {
+        match (button.state, button.decision) {
+            (Held, Some(Decision::Hold)) => Some(dance.hold(button.taps)),
+            (Held, Some(Decision::Tap)) => Some(dance.tap(button.taps)),
+            (Released, Some(Decision::Tap)) if button.has(TAPPED) => Some(dance.tap(button.taps)),
+            _ => None,
+        }
+    }
+
+    /// Switch between N-key rollover and 6 key boot reports.
+    pub fn toggle_nkro(&mut self) {
+        self.nkro = !self.nkro;
+    }
+
+    pub fn nkro(&self) -> bool {
+        self.nkro
+    }
+
+    pub fn toggle_led(&mut self, led: u8) {
+        self.leds ^= 1 << led
+    }
+
+
+    pub fn led_state(&self) -> [bool; LEDS] {
+        let mut locked = self.locked_layers;
+        locked.insert(self.default_layer);
+        let mut leds = [false; LEDS];
+        for (i, led) in leds.iter_mut().enumerate() {
+            let layer = match LAYER_LEDS[i] {
+                Some(layer) => locked.contains(layer),
+                None => false,
+            };
+            *led = self.leds & (1 << i) > 0 || layer;
+        }
+        leds
+    }
+}
+
+/// The highest layer in `layers`, which always has at least the default layer in it.
+fn highest_layer(layers: &Layers) -> u8 {
+    layers.highest().unwrap_or(0)
+}
+
+/// The next active layer below `layer` that a `Transparent` key falls through to. Nothing below
+/// the default layer is looked at.
+fn below(layer: u8, active: &Layers, default_layer: u8) -> Option<u8> {
+    active.highest_below(layer as u16, default_layer)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec::Vec;
+
+    use avr_progmem::progmem;
+
+    use crate::keycode::{k, mods};
+    use crate::layers::Layers;
+    use crate::layout::{COLS, dead_matrix, Flavor, Key, KeyType, Layout, ROWS, TEST_LAYERS};
+    use crate::scan::Scan;
+
+    use super::{ONE_SHOT_TIMEOUT_MS, State, TAP_TOGGLE_TAPS};
+
+    progmem! {
+        static progmem HOLD_MATRIX: [[[KeyType; COLS]; ROWS]; TEST_LAYERS] = {
+            let mut m = dead_matrix();
+            m[0][0][0] = KeyType::OnHold(Key::KeyCode(k::A), 200, Key::KeyCode(k::L_CTRL), None);
+            m
+        };
+    }
+    static HOLD_LAYOUT: Layout = Layout::from(&HOLD_MATRIX);
+
+    // Home row mods on F and D, with J and K to roll onto
+    progmem! {
+        static progmem ROLL_MATRIX: [[[KeyType; COLS]; ROWS]; TEST_LAYERS] = {
+            let mut m = dead_matrix();
+            m[0][0][0] = KeyType::OnHold(Key::KeyCode(k::F), 200, Key::KeyCode(k::L_SHFT), None);
+            m[0][0][1] = KeyType::Instant(Key::KeyCode(k::J));
+            m[0][0][2] = KeyType::OnHold(Key::KeyCode(k::D), 200, Key::KeyCode(k::L_CTRL), Some(Flavor::TapPreferred));
+            m[0][0][3] = KeyType::Instant(Key::KeyCode(k::K));
+            m
+        };
+    }
+    progmem! {
+        static progmem DANCE_MATRIX: [[[KeyType; COLS]; ROWS]; TEST_LAYERS] = {
+            let mut m = dead_matrix();
+            m[0][0][0] = crate::layout_cell!(TD(COLON QUOTE K1, ESC XXX L_CTRL, 200ms));
+            m[0][0][1] = KeyType::Instant(Key::KeyCode(k::J));
+            m
+        };
+    }
+    static DANCE_LAYOUT: Layout = Layout::from(&DANCE_MATRIX);
+
+    progmem! {
+        static progmem COMBO_MATRIX: [[[KeyType; COLS]; ROWS]; TEST_LAYERS] = {
+            let mut m = dead_matrix();
+            m[0][0][0] = KeyType::Instant(Key::KeyCode(k::J));
+            m[0][0][1] = KeyType::Instant(Key::KeyCode(k::K));
+            m[0][0][2] = KeyType::Instant(Key::KeyCode(k::L));
+            m[0][0][3] = KeyType::Instant(Key::KeyCode(k::A));
+            m[0][1][0] = KeyType::Instant(Key::KeyCode(k::Q));
+            m[0][1][1] = KeyType::Instant(Key::KeyCode(k::W));
+            m[1][1][0] = KeyType::Instant(Key::Transparent);
+            m[1][1][1] = KeyType::Instant(Key::Transparent);
+            m[0][3][4] = KeyType::Instant(Key::LayerMo(1));
+            m
+        };
+    }
+    static COMBO_LAYOUT: Layout = Layout::from(&COMBO_MATRIX).with_combos(crate::combos![50ms;
+        (0, 0) (0, 1) => ESC,
+        (0, 0) (0, 1) (0, 2) => TAB,
+        (0, 2) (0, 3) => RETURN release(All),
+        (1, 0) (1, 1) => DELETE layers(1),
+    ]);
+
+    progmem! {
+        static progmem ONE_SHOT_MATRIX: [[[KeyType; COLS]; ROWS]; TEST_LAYERS] = {
+            let mut m = dead_matrix();
+            m[0][0][0] = KeyType::Instant(Key::OneShotMod(mods::L_SHFT));
+            m[0][0][1] = KeyType::Instant(Key::OneShotLayer(1));
+            m[0][0][2] = KeyType::Instant(Key::KeyCode(k::A));
+            m[0][0][3] = KeyType::Instant(Key::KeyCode(k::B));
+            m[1][0][0] = KeyType::Instant(Key::Transparent);
+            m[1][0][1] = KeyType::Instant(Key::Transparent);
+            m[1][0][2] = KeyType::Instant(Key::KeyCode(k::K1));
+            m
+        };
+    }
+    static ONE_SHOT_LAYOUT: Layout = Layout::from(&ONE_SHOT_MATRIX);
+
+    static TAP_PREFERRED_LAYOUT: Layout = Layout::from(&ROLL_MATRIX);
+    static HOLD_ON_PRESS_LAYOUT: Layout = Layout::from(&ROLL_MATRIX).with_flavor(Flavor::HoldOnOtherKeyPress);
+    static PERMISSIVE_LAYOUT: Layout = Layout::from(&ROLL_MATRIX).with_flavor(Flavor::PermissiveHold);
+
+    progmem! {
+        static progmem LAYER_MATRIX: [[[KeyType; COLS]; ROWS]; TEST_LAYERS] = {
+            let mut m = dead_matrix();
+            m[0][0][0] = KeyType::Instant(Key::KeyCode(k::A));
+            m[1][0][0] = KeyType::Instant(Key::KeyCode(k::K1));
+            m[2][0][0] = KeyType::Instant(Key::KeyCode(k::F1));
+            m[3][0][0] = KeyType::Instant(Key::KeyCode(k::ESC));
+            m[0][3][4] = KeyType::Instant(Key::LayerMo(1));
+            m[0][3][7] = KeyType::Instant(Key::LayerMo(2));
+            m[1][3][5] = KeyType::Instant(Key::LayerMo(3));
+            m[0][1][0] = KeyType::Instant(Key::LayerToggle(1));
+            m[0][1][1] = KeyType::Instant(Key::LayerTo(2));
+            m[0][1][2] = KeyType::Instant(Key::LayerTapToggle(1));
+            m[0][1][3] = KeyType::Instant(Key::DefaultLayer(2));
+            m[2][1][3] = KeyType::Instant(Key::DefaultLayer(0));
+            m[1][1][0] = KeyType::Instant(Key::PassThrough(1));
+            m[1][1][1] = KeyType::Instant(Key::PassThrough(1));
+            m[1][1][2] = KeyType::Instant(Key::PassThrough(1));
+            m
+        };
+    }
+    static LAYER_LAYOUT: Layout = Layout::from(&LAYER_MATRIX);
+    progmem! {
+        static progmem TRANSPARENT_MATRIX: [[[KeyType; COLS]; ROWS]; TEST_LAYERS] = {
+            let mut m = dead_matrix();
+            m[0][0][0] = KeyType::Instant(Key::KeyCode(k::A));
+            m[1][0][0] = KeyType::Instant(Key::KeyCode(k::K1));
+            m[2][0][0] = KeyType::Instant(Key::Transparent);
+            m[3][0][0] = KeyType::Instant(Key::PassThrough(2));
+            m[0][0][1] = KeyType::OnHold(Key::KeyCode(k::B), 200, Key::KeyCode(k::L_CTRL), None);
+            m[2][0][1] = KeyType::Instant(Key::Transparent);
+            m[0][0][2] = KeyType::Instant(Key::KeyCode(k::C));
+            m[1][0][2] = KeyType::Instant(Key::PassThrough(2));
+            m[1][0][3] = KeyType::Instant(Key::PassThrough(0));
+            m[0][3][4] = KeyType::Instant(Key::LayerMo(1));
+            m[0][3][7] = KeyType::Instant(Key::LayerMo(2));
+            m[0][3][8] = KeyType::Instant(Key::LayerMo(3));
+            m
+        };
+    }
+    static TRANSPARENT_LAYOUT: Layout = Layout::from(&TRANSPARENT_MATRIX);
+    static TRI_LAYER_LAYOUT: Layout = Layout::from(&LAYER_MATRIX).with_tri_layer(1, 2, 3);
+
+    progmem! {
+        // Past 8 layers, so the layers are looked up across the bytes of `Layers`
+        static progmem MANY_LAYERS_MATRIX: [[[KeyType; COLS]; ROWS]; 17] = {
+            let mut m = [[[KeyType::Instant(Key::Dead); COLS]; ROWS]; 17];
+            m[0][0][0] = KeyType::Instant(Key::KeyCode(k::A));
+            m[10][0][0] = KeyType::Instant(Key::KeyCode(k::K0));
+            m[13][0][0] = KeyType::Instant(Key::KeyCode(k::K3));
+            m[16][0][0] = KeyType::Instant(Key::Transparent);
+            m[16][0][1] = KeyType::Instant(Key::KeyCode(k::K6));
+            m[0][3][4] = KeyType::Instant(Key::LayerMo(13));
+            m[0][3][7] = KeyType::Instant(Key::LayerMo(16));
+            m[0][3][8] = KeyType::Instant(Key::LayerMo(9));
+            m[13][3][5] = KeyType::Instant(Key::LayerMo(16));
+            m[0][1][0] = KeyType::Instant(Key::OneShotLayer(10));
+            m
+        };
+    }
+    static MANY_LAYERS_LAYOUT: Layout = Layout::from(&MANY_LAYERS_MATRIX);
+    static MANY_TRI_LAYER_LAYOUT: Layout = Layout::from(&MANY_LAYERS_MATRIX).with_tri_layer(9, 13, 16);
+
+    fn scan(pressed: &[(usize, usize)]) -> Scan {
+        let mut scan = Scan::new();
+        pressed.iter().for_each(|(r, c)| scan.set_pressed(r, c));
+        scan
+    }
+
+    fn keycodes(state: &State) -> Vec<u8> {
+        state.keys().iter()
+            .filter_map(|k| match k {
+                Key::KeyCode(kc) => Some(*kc),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn on_hold_limit_is_in_ms_regardless_of_tick_rate() {
+        for step in [1, 5, 7, 20, 100] {
+            let mut state = State::with_layout(&HOLD_LAYOUT);
+            let mut now = 0;
+            while now <= 200 {
+                state.tick(&scan(&[(0, 0)]), now);
+                assert!(keycodes(&state).is_empty(), "step {} at {}", step, now);
+                now += step;
+            }
+            state.tick(&scan(&[(0, 0)]), 201);
+            assert_eq!(keycodes(&state), [k::L_CTRL], "step {}", step);
+        }
+    }
+
+    #[test]
+    fn tapped_on_hold_sends_the_tap_key_once_released() {
+        let mut state = State::with_layout(&HOLD_LAYOUT);
+        for now in (0..150).step_by(3) {
+            state.tick(&scan(&[(0, 0)]), now);
+            assert!(keycodes(&state).is_empty());
+        }
+        state.tick(&scan(&[]), 150);
+        assert_eq!(keycodes(&state), [k::A]);
+        state.tick(&scan(&[]), 160);
+        assert!(keycodes(&state).is_empty());
+    }
+
+    #[test]
+    fn on_hold_held_past_the_limit_sends_nothing_on_release() {
+        let mut state = State::with_layout(&HOLD_LAYOUT);
+        state.tick(&scan(&[(0, 0)]), 0);
+        state.tick(&scan(&[(0, 0)]), 300);
+        assert_eq!(keycodes(&state), [k::L_CTRL]);
+        state.tick(&scan(&[]), 301);
+        assert!(keycodes(&state).is_empty());
+    }
+
+    #[test]
+    fn button_times_do_not_wrap_while_idle_or_held() {
+        let mut state = State::with_layout(&HOLD_LAYOUT);
+        state.tick(&scan(&[(0, 0)]), 0);
+        state.tick(&scan(&[]), 100);
+        assert_eq!(keycodes(&state), [k::A]);
+        for now in (110..200_000).step_by(10) {
+            state.tick(&scan(&[]), now);
+            assert!(keycodes(&state).is_empty(), "at {}", now);
+        }
+        for now in (200_000..400_000).step_by(10) {
+            state.tick(&scan(&[(0, 0)]), now);
+            assert_eq!(keycodes(&state), if now > 200_200 { &[k::L_CTRL][..] } else { &[] }, "at {}", now);
+        }
+    }
+
+    #[test]
+    fn roll_stays_a_tap_unless_any_other_key_press_makes_a_hold() {
+        for layout in [&TAP_PREFERRED_LAYOUT, &PERMISSIVE_LAYOUT] {
+            let mut state = State::with_layout(layout);
+            state.tick(&scan(&[(0, 0)]), 0);
+            state.tick(&scan(&[(0, 0), (0, 1)]), 30);
+            assert!(keycodes(&state).is_empty());
+            state.tick(&scan(&[(0, 1)]), 60);
+            assert_eq!(keycodes(&state), [k::F]);
+            state.tick(&scan(&[(0, 1)]), 61);
+            assert_eq!(keycodes(&state), [k::F, k::J]);
+            state.tick(&scan(&[]), 90);
+            assert!(keycodes(&state).is_empty());
+        }
+
+        let mut state = State::with_layout(&HOLD_ON_PRESS_LAYOUT);
+        state.tick(&scan(&[(0, 0)]), 0);
+        assert!(keycodes(&state).is_empty());
+        state.tick(&scan(&[(0, 0), (0, 1)]), 30);
+        assert_eq!(keycodes(&state), [k::L_SHFT]);
+        state.tick(&scan(&[(0, 0), (0, 1)]), 31);
+        assert_eq!(keycodes(&state), [k::L_SHFT, k::J]);
+        state.tick(&scan(&[(0, 1)]), 60);
+        assert_eq!(keycodes(&state), [k::J]);
+    }
+
+    #[test]
+    fn key_tapped_inside_a_permissive_hold_makes_it_a_hold() {
+        let mut state = State::with_layout(&PERMISSIVE_LAYOUT);
+        state.tick(&scan(&[(0, 0)]), 0);
+        state.tick(&scan(&[(0, 0), (0, 1)]), 30);
+        assert!(keycodes(&state).is_empty());
+        state.tick(&scan(&[(0, 0)]), 60);
+        assert_eq!(keycodes(&state), [k::L_SHFT]);
+        state.tick(&scan(&[(0, 0)]), 61);
+        assert_eq!(keycodes(&state), [k::L_SHFT, k::J]);
+        state.tick(&scan(&[(0, 0)]), 62);
+        assert_eq!(keycodes(&state), [k::L_SHFT]);
+        state.tick(&scan(&[]), 90);
+        assert!(keycodes(&state).is_empty());
+
+        let mut state = State::with_layout(&TAP_PREFERRED_LAYOUT);
+        state.tick(&scan(&[(0, 0)]), 0);
+        state.tick(&scan(&[(0, 0), (0, 1)]), 30);
+        state.tick(&scan(&[(0, 0)]), 60);
+        assert!(keycodes(&state).is_empty());
+        state.tick(&scan(&[]), 90);
+        assert_eq!(keycodes(&state), [k::F]);
+        state.tick(&scan(&[]), 91);
+        assert_eq!(keycodes(&state), [k::F, k::J]);
+        state.tick(&scan(&[]), 100);
+        assert!(keycodes(&state).is_empty());
+    }
+
+    #[test]
+    fn keys_pressed_before_the_hold_tap_do_not_decide_it() {
+        let mut state = State::with_layout(&HOLD_ON_PRESS_LAYOUT);
+        state.tick(&scan(&[(0, 1)]), 0);
+        state.tick(&scan(&[(0, 1), (0, 0)]), 30);
+        assert_eq!(keycodes(&state), [k::J]);
+        state.tick(&scan(&[(0, 1)]), 60);
+        assert_eq!(keycodes(&state), [k::F, k::J]);
+    }
+
+    #[test]
+    fn flavor_of_the_key_wins_over_the_layout() {
+        let mut state = State::with_layout(&HOLD_ON_PRESS_LAYOUT);
+        state.tick(&scan(&[(0, 2)]), 0);
+        state.tick(&scan(&[(0, 2), (0, 1)]), 30);
+        assert!(keycodes(&state).is_empty());
+        state.tick(&scan(&[(0, 1)]), 60);
+        assert_eq!(keycodes(&state), [k::D]);
+        state.tick(&scan(&[(0, 1)]), 61);
+        assert_eq!(keycodes(&state), [k::J, k::D]);
+        state.tick(&scan(&[(0, 1), (0, 2)]), 100);
+        state.tick(&scan(&[(0, 1), (0, 2)]), 301);
+        assert_eq!(keycodes(&state), [k::J, k::L_CTRL]);
+    }
+
+    #[test]
+    fn keys_pressed_during_a_hold_tap_go_out_in_press_order() {
+        let mut state = State::with_layout(&TAP_PREFERRED_LAYOUT);
+        state.tick(&scan(&[(0, 0)]), 0);
+        state.tick(&scan(&[(0, 0), (0, 3)]), 20);
+        state.tick(&scan(&[(0, 0), (0, 3), (0, 1)]), 40);
+        assert!(keycodes(&state).is_empty());
+        let mut sent = Vec::new();
+        for now in 60..64 {
+            state.tick(&scan(&[(0, 3), (0, 1)]), now);
+            for key in keycodes(&state) {
+                if !sent.contains(&key) {
+                    sent.push(key);
+                }
+            }
+        }
+        assert_eq!(sent, [k::F, k::K, k::J]);
+    }
+
+    #[test]
+    fn held_back_keys_wait_for_the_hold_time() {
+        let mut state = State::with_layout(&TAP_PREFERRED_LAYOUT);
+        state.tick(&scan(&[(0, 0)]), 0);
+        state.tick(&scan(&[(0, 0), (0, 1)]), 30);
+        state.tick(&scan(&[(0, 0), (0, 1)]), 200);
+        assert!(keycodes(&state).is_empty());
+        state.tick(&scan(&[(0, 0), (0, 1)]), 201);
+        assert_eq!(keycodes(&state), [k::L_SHFT]);
+        state.tick(&scan(&[(0, 0), (0, 1)]), 202);
+        assert_eq!(keycodes(&state), [k::L_SHFT, k::J]);
+    }
+
+    /// Tap the tap dance once for every `(press, release)`.
+    fn dance(state: &mut State, taps: &[(u32, u32)]) {
+        for (press, release) in taps {
+            state.tick(&scan(&[(0, 0)]), *press);
+            state.tick(&scan(&[]), *release);
+        }
+    }
+
+    #[test]
+    fn tap_dance_sends_the_key_for_the_number_of_taps_once_the_term_is_over() {
+        let mut state = State::with_layout(&DANCE_LAYOUT);
+        dance(&mut state, &[(0, 50)]);
+        state.tick(&scan(&[]), 250);
+        assert!(keycodes(&state).is_empty());
+        state.tick(&scan(&[]), 251);
+        assert_eq!(keycodes(&state), [k::COLON]);
+        state.tick(&scan(&[]), 252);
+        assert!(keycodes(&state).is_empty());
+
+        dance(&mut state, &[(300, 350), (400, 450)]);
+        assert!(keycodes(&state).is_empty());
+        state.tick(&scan(&[]), 651);
+        assert_eq!(keycodes(&state), [k::QUOTE]);
+    }
+
+    #[test]
+    fn tap_dance_ends_at_its_last_tap() {
+        let mut state = State::with_layout(&DANCE_LAYOUT);
+        dance(&mut state, &[(0, 50), (100, 150), (200, 250)]);
+        assert_eq!(keycodes(&state), [k::K1]);
+    }
+
+    #[test]
+    fn tap_dance_held_sends_the_hold_key_for_that_press() {
+        let mut state = State::with_layout(&DANCE_LAYOUT);
+        state.tick(&scan(&[(0, 0)]), 0);
+        state.tick(&scan(&[(0, 0)]), 201);
+        assert_eq!(keycodes(&state), [k::ESC]);
+        state.tick(&scan(&[]), 250);
+        assert!(keycodes(&state).is_empty());
+
+        dance(&mut state, &[(300, 350), (400, 450)]);
+        state.tick(&scan(&[(0, 0)]), 500);
+        state.tick(&scan(&[(0, 0)]), 701);
+        assert_eq!(keycodes(&state), [k::L_CTRL]);
+    }
+
+    #[test]
+    fn tap_dance_held_without_a_hold_key_holds_the_tap_key() {
+        let mut state = State::with_layout(&DANCE_LAYOUT);
+        dance(&mut state, &[(0, 50)]);
+        state.tick(&scan(&[(0, 0)]), 100);
+        assert!(keycodes(&state).is_empty());
+        state.tick(&scan(&[(0, 0)]), 301);
+        assert_eq!(keycodes(&state), [k::QUOTE]);
+        state.tick(&scan(&[(0, 0)]), 400);
+        assert_eq!(keycodes(&state), [k::QUOTE]);
+    }
+
+    #[test]
+    fn another_key_ends_the_tap_dance_and_goes_out_after_it() {
+        let mut state = State::with_layout(&DANCE_LAYOUT);
+        dance(&mut state, &[(0, 50)]);
+        state.tick(&scan(&[(0, 1)]), 80);
+        assert_eq!(keycodes(&state), [k::COLON]);
+        state.tick(&scan(&[(0, 1)]), 81);
+        assert_eq!(keycodes(&state), [k::J]);
+    }
+
+    #[test]
+    fn combo_sends_its_key_instead_of_its_buttons() {
+        let mut state = State::with_layout(&COMBO_LAYOUT);
+        state.tick(&scan(&[(0, 0)]), 0);
+        state.tick(&scan(&[(0, 0), (0, 1)]), 10);
+        // It could still be the bigger combo
+        state.tick(&scan(&[(0, 0), (0, 1)]), 50);
+        assert!(keycodes(&state).is_empty());
+        state.tick(&scan(&[(0, 0), (0, 1)]), 51);
+        assert_eq!(keycodes(&state), [k::ESC]);
+        // Released with the first button, the other one stays quiet
+        state.tick(&scan(&[(0, 0)]), 80);
+        assert!(keycodes(&state).is_empty());
+        state.tick(&scan(&[(0, 0)]), 200);
+        assert!(keycodes(&state).is_empty());
+    }
+
+    #[test]
+    fn longest_combo_wins() {
+        let mut state = State::with_layout(&COMBO_LAYOUT);
+        state.tick(&scan(&[(0, 1)]), 0);
+        state.tick(&scan(&[(0, 1), (0, 0)]), 10);
+        state.tick(&scan(&[(0, 1), (0, 0), (0, 2)]), 20);
+        assert_eq!(keycodes(&state), [k::TAB]);
+    }
+
+    #[test]
+    fn combo_buttons_not_pressed_together_go_out_as_themselves() {
+        let mut state = State::with_layout(&COMBO_LAYOUT);
+        state.tick(&scan(&[(0, 0)]), 0);
+        assert!(keycodes(&state).is_empty());
+        state.tick(&scan(&[(0, 0)]), 51);
+        assert_eq!(keycodes(&state), [k::J]);
+        state.tick(&scan(&[(0, 0), (0, 1)]), 60);
+        state.tick(&scan(&[(0, 0), (0, 1)]), 111);
+        assert_eq!(keycodes(&state), [k::J, k::K]);
+
+        // Tapped before the term is over
+        let mut state = State::with_layout(&COMBO_LAYOUT);
+        state.tick(&scan(&[(0, 0)]), 0);
+        state.tick(&scan(&[]), 20);
+        assert_eq!(keycodes(&state), [k::J]);
+    }
+
+    #[test]
+    fn other_key_pressed_in_between_stops_the_combo() {
+        let mut state = State::with_layout(&COMBO_LAYOUT);
+        state.tick(&scan(&[(0, 0)]), 0);
+        state.tick(&scan(&[(0, 0), (0, 3)]), 10);
+        assert_eq!(keycodes(&state), [k::J]);
+        state.tick(&scan(&[(0, 0), (0, 3), (0, 1)]), 11);
+        assert_eq!(keycodes(&state), [k::J, k::A]);
+        state.tick(&scan(&[(0, 0), (0, 3), (0, 1)]), 62);
+        assert_eq!(keycodes(&state), [k::J, k::K, k::A]);
+    }
+
+    #[test]
+    fn combo_released_on_all_stays_held_until_the_last_button() {
+        let mut state = State::with_layout(&COMBO_LAYOUT);
+        state.tick(&scan(&[(0, 2)]), 0);
+        state.tick(&scan(&[(0, 2), (0, 3)]), 10);
+        assert_eq!(keycodes(&state), [k::RETURN]);
+        state.tick(&scan(&[(0, 3)]), 100);
+        assert_eq!(keycodes(&state), [k::RETURN]);
+        state.tick(&scan(&[]), 110);
+        assert!(keycodes(&state).is_empty());
+    }
+
+    #[test]
+    fn combo_only_works_on_its_layers() {
+        let mut state = State::with_layout(&COMBO_LAYOUT);
+        state.tick(&scan(&[(1, 0), (1, 1)]), 0);
+        assert_eq!(keycodes(&state), [k::Q, k::W]);
+
+        let mut state = State::with_layout(&COMBO_LAYOUT);
+        state.tick(&scan(&[(3, 4)]), 0);
+        state.tick(&scan(&[(3, 4), (1, 0), (1, 1)]), 10);
+        assert_eq!(keycodes(&state), [k::DELETE]);
+    }
+
+    #[test]
+    fn tapped_one_shot_mod_goes_with_the_next_key_only() {
+        let mut state = State::with_layout(&ONE_SHOT_LAYOUT);
+        let mut now = 0;
+        tap(&mut state, (0, 0), &mut now);
+        assert_eq!(state.one_shot_mods(), 0);
+        state.tick(&scan(&[(0, 2)]), 30);
+        assert_eq!(keycodes(&state), [k::A]);
+        assert_eq!(state.one_shot_mods(), mods::L_SHFT);
+        state.tick(&scan(&[(0, 2)]), 100);
+        assert_eq!(state.one_shot_mods(), mods::L_SHFT);
+        state.tick(&scan(&[]), 110);
+        assert_eq!(state.one_shot_mods(), 0);
+        state.tick(&scan(&[(0, 3)]), 120);
+        assert_eq!(state.one_shot_mods(), 0);
+    }
+
+    #[test]
+    fn held_one_shot_mod_is_a_plain_modifier() {
+        let mut state = State::with_layout(&ONE_SHOT_LAYOUT);
+        state.tick(&scan(&[(0, 0)]), 0);
+        assert!(matches!(state.keys()[..], [Key::OneShotMod(mods::L_SHFT)]));
+        state.tick(&scan(&[(0, 0), (0, 2)]), 20);
+        state.tick(&scan(&[(0, 0)]), 40);
+        state.tick(&scan(&[]), 60);
+        state.tick(&scan(&[(0, 3)]), 80);
+        assert_eq!(state.one_shot_mods(), 0);
+
+        // Held too long to be a tap
+        let mut state = State::with_layout(&ONE_SHOT_LAYOUT);
+        state.tick(&scan(&[(0, 0)]), 0);
+        state.tick(&scan(&[]), 300);
+        state.tick(&scan(&[(0, 3)]), 320);
+        assert_eq!(state.one_shot_mods(), 0);
+    }
+
+    #[test]
+    fn one_shot_mod_tapped_twice_is_locked_until_tapped_again() {
+        let mut state = State::with_layout(&ONE_SHOT_LAYOUT);
+        let mut now = 0;
+        tap(&mut state, (0, 0), &mut now);
+        tap(&mut state, (0, 0), &mut now);
+        assert_eq!(state.one_shot_mods(), mods::L_SHFT);
+        tap(&mut state, (0, 2), &mut now);
+        tap(&mut state, (0, 3), &mut now);
+        assert_eq!(state.one_shot_mods(), mods::L_SHFT);
+        tap(&mut state, (0, 0), &mut now);
+        assert_eq!(state.one_shot_mods(), 0);
+    }
+
+    #[test]
+    fn one_shot_times_out() {
+        let mut state = State::with_layout(&ONE_SHOT_LAYOUT);
+        let mut now = 0;
+        tap(&mut state, (0, 0), &mut now);
+        tap(&mut state, (0, 1), &mut now);
+        now += ONE_SHOT_TIMEOUT_MS as u32 + 1;
+        state.tick(&scan(&[(0, 2)]), now);
+        assert_eq!(keycodes(&state), [k::A]);
+        assert_eq!(state.one_shot_mods(), 0);
+    }
+
+    #[test]
+    fn tapped_one_shot_layer_is_on_for_the_next_key_only() {
+        let mut state = State::with_layout(&ONE_SHOT_LAYOUT);
+        let mut now = 0;
+        tap(&mut state, (0, 1), &mut now);
+        assert_eq!(state.active_layers(), Layers::of(&[0, 1]));
+        state.tick(&scan(&[(0, 2)]), 30);
+        assert_eq!(keycodes(&state), [k::K1]);
+        state.tick(&scan(&[(0, 2)]), 100);
+        assert_eq!(keycodes(&state), [k::K1]);
+        state.tick(&scan(&[]), 110);
+        assert_eq!(state.active_layers(), Layers::of(&[0]));
+        state.tick(&scan(&[(0, 2)]), 120);
+        assert_eq!(keycodes(&state), [k::A]);
+    }
+
+    #[test]
+    fn held_one_shot_layer_is_momentary() {
+        let mut state = State::with_layout(&ONE_SHOT_LAYOUT);
+        state.tick(&scan(&[(0, 1)]), 0);
+        state.tick(&scan(&[(0, 1), (0, 2)]), 20);
+        assert_eq!(keycodes(&state), [k::K1]);
+        state.tick(&scan(&[(0, 1)]), 40);
+        state.tick(&scan(&[]), 60);
+        assert_eq!(state.active_layers(), Layers::of(&[0]));
+    }
+
+    #[test]
+    fn one_shot_layer_tapped_twice_is_locked_until_tapped_again() {
+        let mut state = State::with_layout(&ONE_SHOT_LAYOUT);
+        let mut now = 0;
+        tap(&mut state, (0, 1), &mut now);
+        tap(&mut state, (0, 1), &mut now);
+        tap(&mut state, (0, 2), &mut now);
+        assert_eq!(state.active_layers(), Layers::of(&[0, 1]));
+        assert_eq!(state.led_state(), [true, false, false]);
+        tap(&mut state, (0, 1), &mut now);
+        assert_eq!(state.active_layers(), Layers::of(&[0]));
+    }
+
+    #[test]
+    fn highest_held_layer_wins_instead_of_adding_up() {
+        let mut state = State::with_layout(&LAYER_LAYOUT);
+        state.tick(&scan(&[(3, 4), (3, 7), (0, 0)]), 0);
+        assert_eq!(state.active_layers(), Layers::of(&[0, 1, 2]));
+        assert_eq!(keycodes(&state), [k::F1]);
+        state.tick(&scan(&[(3, 7), (0, 0)]), 1);
+        assert_eq!(keycodes(&state), [k::F1]);
+        state.tick(&scan(&[(0, 0)]), 2);
+        assert_eq!(state.active_layers(), Layers::of(&[0]));
+        assert_eq!(keycodes(&state), [k::A]);
+    }
+
+    #[test]
+    fn tri_layer_turns_on_while_both_layers_are_held() {
+        let mut state = State::with_layout(&TRI_LAYER_LAYOUT);
+        state.tick(&scan(&[(3, 4), (0, 0)]), 0);
+        assert_eq!(keycodes(&state), [k::K1]);
+        state.tick(&scan(&[(3, 4), (3, 7), (0, 0)]), 1);
+        assert_eq!(state.active_layers(), Layers::of(&[0, 1, 2, 3]));
+        assert_eq!(keycodes(&state), [k::ESC]);
+        state.tick(&scan(&[(3, 7), (0, 0)]), 2);
+        assert_eq!(keycodes(&state), [k::F1]);
+    }
+
+    #[test]
+    fn layer_keys_work_from_the_layer_they_are_on() {
+        // Layer 1 has Dead where the LayerMo(1) is, it still stays on while held
+        let mut state = State::with_layout(&LAYER_LAYOUT);
+        state.tick(&scan(&[(3, 4), (3, 5), (0, 0)]), 0);
+        assert_eq!(state.active_layers(), Layers::of(&[0, 1, 3]));
+        assert_eq!(keycodes(&state), [k::ESC]);
+        // Without layer 1 the LayerMo(3) isn't there
+        state.tick(&scan(&[(3, 5), (0, 0)]), 1);
+        assert_eq!(keycodes(&state), [k::A]);
+    }
+
+    /// Press and release `key` with `now` going up by 10 ms for each tick.
+    fn tap(state: &mut State, key: (usize, usize), now: &mut u32) {
+        *now += 10;
+        state.tick(&scan(&[key]), *now);
+        *now += 10;
+        state.tick(&scan(&[]), *now);
+    }
+
+    #[test]
+    fn layer_toggle_stays_on_until_pressed_again() {
+        let mut state = State::with_layout(&LAYER_LAYOUT);
+        let mut now = 0;
+        tap(&mut state, (1, 0), &mut now);
+        assert_eq!(state.active_layers(), Layers::of(&[0, 1]));
+        assert_eq!(state.led_state(), [true, false, false]);
+        // Held for many ticks it still only toggles once
+        for _ in 0..10 {
+            now += 1;
+            state.tick(&scan(&[(1, 0)]), now);
+        }
+        state.tick(&scan(&[]), now + 1);
+        assert_eq!(state.active_layers(), Layers::of(&[0]));
+        assert_eq!(state.led_state(), [false, false, false]);
+    }
+
+    #[test]
+    fn layer_to_turns_off_the_other_toggled_layers() {
+        let mut state = State::with_layout(&LAYER_LAYOUT);
+        let mut now = 0;
+        tap(&mut state, (1, 0), &mut now);
+        tap(&mut state, (1, 1), &mut now);
+        assert_eq!(state.active_layers(), Layers::of(&[0, 2]));
+        state.tick(&scan(&[(0, 0)]), now + 1);
+        assert_eq!(keycodes(&state), [k::F1]);
+    }
+
+    #[test]
+    fn layer_tap_toggle_is_momentary_when_held() {
+        let mut state = State::with_layout(&LAYER_LAYOUT);
+        state.tick(&scan(&[(1, 2), (0, 0)]), 0);
+        assert_eq!(keycodes(&state), [k::K1]);
+        state.tick(&scan(&[(1, 2), (0, 0)]), 500);
+        state.tick(&scan(&[(0, 0)]), 501);
+        assert_eq!(keycodes(&state), [k::A]);
+    }
+
+    #[test]
+    fn layer_tap_toggle_toggles_after_enough_taps() {
+        let mut state = State::with_layout(&LAYER_LAYOUT);
+        let mut now = 0;
+        for _ in 1..TAP_TOGGLE_TAPS {
+            tap(&mut state, (1, 2), &mut now);
+            assert_eq!(state.active_layers(), Layers::of(&[0]));
+        }
+        tap(&mut state, (1, 2), &mut now);
+        assert_eq!(state.active_layers(), Layers::of(&[0, 1]));
+        for _ in 0..TAP_TOGGLE_TAPS {
+            tap(&mut state, (1, 2), &mut now);
+        }
+        assert_eq!(state.active_layers(), Layers::of(&[0]));
+    }
+
+    #[test]
+    fn layer_tap_toggle_starts_over_after_a_pause() {
+        let mut state = State::with_layout(&LAYER_LAYOUT);
+        let mut now = 0;
+        for _ in 1..TAP_TOGGLE_TAPS {
+            tap(&mut state, (1, 2), &mut now);
+        }
+        now += 1000;
+        tap(&mut state, (1, 2), &mut now);
+        assert_eq!(state.active_layers(), Layers::of(&[0]));
+    }
+
+    #[test]
+    fn default_layer_replaces_the_base_layer() {
+        let mut state = State::with_layout(&LAYER_LAYOUT);
+        let mut now = 0;
+        tap(&mut state, (1, 3), &mut now);
+        assert_eq!(state.default_layer(), 2);
+        assert_eq!(state.active_layers(), Layers::of(&[2]));
+        assert_eq!(state.led_state(), [false, true, false]);
+        state.tick(&scan(&[(0, 0)]), now + 1);
+        assert_eq!(keycodes(&state), [k::F1]);
+        // Layer 2 has the way back
+        tap(&mut state, (1, 3), &mut now);
+        assert_eq!(state.default_layer(), 0);
+    }
+
+    #[test]
+    fn transparent_skips_layers_that_are_not_active() {
+        let mut state = State::with_layout(&TRANSPARENT_LAYOUT);
+        state.tick(&scan(&[(3, 7), (0, 0)]), 0);
+        assert_eq!(keycodes(&state), [k::A]);
+        state.tick(&scan(&[(3, 4), (3, 7), (0, 0)]), 1);
+        assert_eq!(keycodes(&state), [k::K1]);
+    }
+
+    #[test]
+    fn pass_through_goes_down_a_fixed_number_of_layers() {
+        let mut state = State::with_layout(&TRANSPARENT_LAYOUT);
+        state.tick(&scan(&[(3, 8), (0, 0)]), 0);
+        assert_eq!(keycodes(&state), [k::K1]);
+    }
+
+    #[test]
+    fn pass_through_below_layer_0_is_dead() {
+        let mut state = State::with_layout(&TRANSPARENT_LAYOUT);
+        state.tick(&scan(&[(3, 4), (0, 2)]), 0);
+        assert!(keycodes(&state).is_empty());
+    }
+
+    #[test]
+    fn pass_through_to_its_own_layer_is_dead() {
+        let mut state = State::with_layout(&TRANSPARENT_LAYOUT);
+        state.tick(&scan(&[(3, 4), (0, 3)]), 0);
+        assert!(keycodes(&state).is_empty());
+    }
+
+    #[test]
+    fn transparent_stops_at_the_default_layer() {
+        let mut state = State::with_layout(&TRANSPARENT_LAYOUT);
+        state.set_default_layer(1);
+        state.tick(&scan(&[(3, 7), (0, 0)]), 0);
+        assert_eq!(keycodes(&state), [k::K1]);
+
+        let mut state = State::with_layout(&TRANSPARENT_LAYOUT);
+        state.set_default_layer(2);
+        state.tick(&scan(&[(0, 0)]), 0);
+        assert!(keycodes(&state).is_empty());
+    }
+
+    #[test]
+    fn on_hold_under_a_transparent_key_still_waits_for_its_limit() {
+        let mut state = State::with_layout(&TRANSPARENT_LAYOUT);
+        let button_state = state.tick(&scan(&[(3, 7), (0, 1)]), 0);
+        assert!(button_state[1] == super::Pressed);
+        assert!(keycodes(&state).is_empty());
+        state.tick(&scan(&[(3, 7), (0, 1)]), 300);
+        assert_eq!(keycodes(&state), [k::L_CTRL]);
+    }
+
+    #[test]
+    fn layer_mo_reaches_layers_past_8() {
+        let mut state = State::with_layout(&MANY_LAYERS_LAYOUT);
+        state.tick(&scan(&[(3, 4), (3, 7), (0, 1)]), 0);
+        assert_eq!(state.active_layers(), Layers::of(&[0, 13, 16]));
+        assert_eq!(keycodes(&state), [k::K6]);
+        state.tick(&scan(&[]), 1);
+        assert_eq!(state.active_layers(), Layers::of(&[0]));
+
+        // A layer key on a layer past 8 turns on the ones above it
+        state.tick(&scan(&[(3, 4), (3, 5), (0, 1)]), 2);
+        assert_eq!(state.active_layers(), Layers::of(&[0, 13, 16]));
+        assert_eq!(keycodes(&state), [k::K6]);
+    }
+
+    #[test]
+    fn transparent_falls_through_across_bytes_of_layers() {
+        let mut state = State::with_layout(&MANY_LAYERS_LAYOUT);
+        state.tick(&scan(&[(3, 4), (3, 7), (0, 0)]), 0);
+        assert_eq!(keycodes(&state), [k::K3]);
+        state.tick(&scan(&[(3, 7), (0, 0)]), 1);
+        assert_eq!(keycodes(&state), [k::A]);
+    }
+
+    #[test]
+    fn tri_layer_works_past_8_layers() {
+        let mut state = State::with_layout(&MANY_TRI_LAYER_LAYOUT);
+        state.tick(&scan(&[(3, 8), (3, 4), (0, 1)]), 0);
+        assert_eq!(state.active_layers(), Layers::of(&[0, 9, 13, 16]));
+        assert_eq!(keycodes(&state), [k::K6]);
+        state.tick(&scan(&[(3, 8), (0, 1)]), 1);
+        assert_eq!(state.active_layers(), Layers::of(&[0, 9]));
+    }
+
+    #[test]
+    fn one_shot_layer_past_8_is_on_for_the_next_key_only() {
+        let mut state = State::with_layout(&MANY_LAYERS_LAYOUT);
+        let mut now = 0;
+        tap(&mut state, (1, 0), &mut now);
+        assert_eq!(state.active_layers(), Layers::of(&[0, 10]));
+        state.tick(&scan(&[(0, 0)]), now + 10);
+        assert_eq!(keycodes(&state), [k::K0]);
+        state.tick(&scan(&[]), now + 20);
+        assert_eq!(state.active_layers(), Layers::of(&[0]));
+        state.tick(&scan(&[(0, 0)]), now + 30);
+        assert_eq!(keycodes(&state), [k::A]);
+    }
+}
@@ -0,0 +1,61 @@
+use usb_device::class_prelude::*;
+use usb_device::control::{Recipient, RequestType};
+use waddle_core::report::{DEFAULT_IDLE_MS, KEYBOARD_REPORT_ID};
+
+const HID_REQ_GET_IDLE: u8 = 0x02;
+const HID_REQ_SET_IDLE: u8 = 0x0a;
+
+/// Answers GET_IDLE and SET_IDLE for the keyboard interfaces.
+///
+/// `usbd_hid` rejects GET_IDLE and accepts SET_IDLE without doing anything with it. This class
+/// has no interfaces of its own, it only needs to come before the HID classes in the list given
+/// to `UsbDevice::poll` so it sees the requests first.
+///
+/// There is one idle rate for the whole keyboard. It applies to the keyboard report, which is the
+/// only one a host in boot protocol sees. SET_IDLE for the other report IDs is accepted but
+/// ignored, the extra reports are only ever sent on change.
+pub struct IdleClass {
+    /// In the 4 ms units used on the wire. 0 means only send reports when something changes.
+    idle: u8,
+}
+
+impl IdleClass {
+    pub fn new() -> Self {
+        Self { idle: (DEFAULT_IDLE_MS / 4) as u8 }
+    }
+
+    /// The idle rate in ms, 0 if reports should only be sent on change.
+    pub fn idle_ms(&self) -> u16 {
+        self.idle as u16 * 4
+    }
+
+    fn is_idle_request(req: &control::Request, request: u8) -> bool {
+        req.request_type == RequestType::Class
+            && req.recipient == Recipient::Interface
+            && req.request == request
+    }
+}
+
+impl<B: UsbBus> UsbClass<B> for IdleClass {
+    fn reset(&mut self) {
+        self.idle = (DEFAULT_IDLE_MS / 4) as u8;
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<B>) {
+        if Self::is_idle_request(xfer.request(), HID_REQ_GET_IDLE) {
+            xfer.accept_with(&[self.idle]).ok();
+        }
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<B>) {
+        let req = xfer.request();
+        if Self::is_idle_request(req, HID_REQ_SET_IDLE) {
+            // High byte is the duration, low byte the report ID it applies to, 0 for all
+            let report_id = (req.value & 0xFF) as u8;
+            if report_id == 0 || report_id == KEYBOARD_REPORT_ID {
+                self.idle = (req.value >> 8) as u8;
+            }
+            xfer.accept().ok();
+        }
+    }
+}
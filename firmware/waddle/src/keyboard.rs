@@ -1,31 +1,41 @@
-use arduino_hal::delay_ms;
-use arduino_hal::hal::port::{Dynamic, PD1, PF4};
-use arduino_hal::port::mode::{Floating, Input, Output, PullUp};
+use core::cell::{Cell, RefCell};
+
+use arduino_hal::Eeprom;
+use arduino_hal::port::mode::{Input, Output, PullUp};
 use arduino_hal::port::Pin;
 use atmega_usbd::UsbBus;
-use heapless::Vec;
+use avr_device::interrupt;
+use avr_device::interrupt::Mutex;
+use heapless::{Deque, Vec};
 use usb_device::device::{UsbDevice, UsbDeviceState};
-use usbd_hid::descriptor::KeyboardReport;
-use usbd_hid::hid_class::HIDClass;
+use usbd_hid::hid_class::{HIDClass, HidProtocolMode};
+use waddle_core::controller::{Controller, ScanType};
+use waddle_core::hal::{KeyboardHal, MatrixPin};
+use waddle_core::layout::{COLS, LEDS, ROWS};
+use waddle_core::report::{DEFAULT_IDLE_MS, MAX_REPORT_LEN, Protocol, Report};
 
-use crate::keycode::k;
-use crate::layout::{BUTTONS, COLS, Key, LAYERS, Layout, LAYOUT, LEDS, NUM_CHUNKS, ROWS};
-use crate::layout::Key::KeyCode;
-use crate::position::position::Position;
-use crate::scan::Scan;
-use crate::state::{ButtonState, State};
-use crate::state::ButtonState::Released;
-use crate::vec;
+use crate::idle::IdleClass;
+use crate::millis;
 
 pub type RowPinType = Pin<Output>;
 pub type ColPinType = Pin<Input<PullUp>>;
 
-pub const DELAY_MS: u16 = 5;
+/// Where in EEPROM the default layer is kept.
+const DEFAULT_LAYER_ADDRESS: u16 = 0;
 
-pub enum ScanType {
-    ROW2COL,
-    COL2ROW,
-}
+/// How many reports the main loop can get ahead of the host before it has to wait for it.
+const REPORT_QUEUE: usize = 8;
+
+/// Reports built by the main loop, waiting for the USB interrupt to hand them to the host.
+static REPORTS: Mutex<RefCell<Deque<Report, REPORT_QUEUE>>> = Mutex::new(RefCell::new(Deque::new()));
+/// Whether the host has configured us. No point in scanning before it has.
+static CONFIGURED: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
+/// The protocol the host has selected on the boot interface.
+static PROTOCOL: Mutex<Cell<Protocol>> = Mutex::new(Cell::new(Protocol::Report));
+/// The idle rate the host has set, in ms.
+static IDLE_MS: Mutex<Cell<u16>> = Mutex::new(Cell::new(DEFAULT_IDLE_MS));
+/// The last led output report from the host.
+static HOST_LEDS: Mutex<Cell<u8>> = Mutex::new(Cell::new(0));
 
 enum EitherPin {
     Input(Pin<Input<PullUp>>),
@@ -33,25 +43,105 @@ enum EitherPin {
     None,
 }
 
-
-pub struct Keyboard {
-    usb_device: UsbDevice<'static, UsbBus>,
-    hid_class: HIDClass<'static, UsbBus>,
-    scan_type: ScanType,
+/// The pins of the pro micro, handed to the [`Controller`] as its [`KeyboardHal`].
+/// Reports are queued for the USB interrupt rather than pushed directly.
+struct Hardware {
     rows: Vec<EitherPin, ROWS>,
     cols: Vec<EitherPin, COLS>,
     leds: Vec<EitherPin, LEDS>,
-    state: State,
-    last_button_state: [ButtonState; BUTTONS],
+    eeprom: Eeprom,
+}
+
+/// The USB side of the keyboard. Owned by the USB interrupts.
+///
+/// `boot_class` is the boot keyboard interface. It only sends reports when the host has selected
+/// the boot protocol, but always receives the host's leds. Everything else goes out on
+/// `report_class`, see `waddle_core::report`. `idle` answers the idle requests for both.
+pub struct Keyboard {
+    usb_device: UsbDevice<'static, UsbBus>,
+    idle: IdleClass,
+    boot_class: HIDClass<'static, UsbBus>,
+    report_class: HIDClass<'static, UsbBus>,
+}
+
+/// The matrix side of the keyboard. Owned by the main loop.
+pub struct Matrix {
+    hardware: Hardware,
+    controller: Controller,
 }
 
 impl Keyboard {
-    pub fn row2col(
+    pub fn new(
         usb_device: UsbDevice<'static, UsbBus>,
-        hid_class: HIDClass<'static, UsbBus>,
+        boot_class: HIDClass<'static, UsbBus>,
+        report_class: HIDClass<'static, UsbBus>,
+    ) -> Self {
+        Self {
+            usb_device,
+            idle: IdleClass::new(),
+            boot_class,
+            report_class,
+        }
+    }
+
+    /// Service the bus and hand any queued reports to the host.
+    /// Called from the USB interrupts, so it must never block.
+    pub fn poll(&mut self) {
+        // The idle class goes first so it gets to answer the idle requests before usbd_hid does
+        if self.usb_device.poll(&mut [&mut self.idle, &mut self.boot_class, &mut self.report_class]) {
+            let mut report_buf = [0u8; 1];
+            if self.boot_class.pull_raw_output(&mut report_buf).is_ok() {
+                interrupt::free(|cs| HOST_LEDS.borrow(cs).set(report_buf[0]));
+            }
+        }
+        let configured = self.usb_device.state() == UsbDeviceState::Configured;
+        let protocol = match self.boot_class.get_protocol_mode() {
+            Ok(HidProtocolMode::Boot) => Protocol::Boot,
+            _ => Protocol::Report,
+        };
+        let idle_ms = self.idle.idle_ms();
+        interrupt::free(|cs| {
+            CONFIGURED.borrow(cs).set(configured);
+            PROTOCOL.borrow(cs).set(protocol);
+            IDLE_MS.borrow(cs).set(idle_ms);
+        });
+        self.flush();
+    }
+
+    /// Push queued reports until the queue is empty or the endpoint is busy.
+    ///
+    /// In boot protocol only the keyboard report is sent, on the boot interface. A host in boot
+    /// protocol never reads the report interface, so anything queued for it would block the queue
+    /// and is dropped instead.
+    pub fn flush(&mut self) {
+        interrupt::free(|cs| {
+            let protocol = PROTOCOL.borrow(cs).get();
+            let mut reports = REPORTS.borrow(cs).borrow_mut();
+            while let Some(report) = reports.front() {
+                let pushed = match (report, protocol) {
+                    (Report::Keyboard(kr), Protocol::Boot) => self.boot_class.push_raw_input(&kr.to_boot_bytes()),
+                    (_, Protocol::Boot) => Ok(0),
+                    _ => {
+                        let mut buf = [0u8; MAX_REPORT_LEN];
+                        let len = report.write(&mut buf);
+                        self.report_class.push_raw_input(&buf[..len])
+                    }
+                };
+                match pushed {
+                    Ok(_) => { reports.pop_front(); }
+                    Err(_) => break,
+                }
+            }
+        });
+    }
+}
+
+impl Matrix {
+    pub fn row2col(
         mut rows: Vec<Pin<Output>, ROWS>,
         mut cols: Vec<Pin<Input<PullUp>>, COLS>,
         mut leds: Vec<Pin<Output>, LEDS>,
+        eeprom: Eeprom,
     ) -> Self {
         let mut row_pins: Vec<EitherPin, ROWS> = Vec::new();
         let mut col_pins: Vec<EitherPin, COLS> = Vec::new();
@@ -66,24 +156,22 @@ impl Keyboard {
         for (i, pin) in leds.into_iter().enumerate() {
             led_pins.insert(i, EitherPin::Output(pin));
         }
-        let mut state = State::new(); // Need to be broken out. If inlined atmega32u4 panics
+        let mut controller = Controller::new(ScanType::ROW2COL); // Need to be broken out. If inlined atmega32u4 panics
         Self {
-            usb_device,
-            hid_class,
-            scan_type: ScanType::ROW2COL,
-            rows: row_pins,
-            cols: col_pins,
-            leds: led_pins,
-            state: state,
-            last_button_state: [Released; BUTTONS],
+            hardware: Hardware {
+                rows: row_pins,
+                cols: col_pins,
+                leds: led_pins,
+                eeprom,
+            },
+            controller,
         }
     }
     pub fn col2row(
-        usb_device: UsbDevice<'static, UsbBus>,
-        hid_class: HIDClass<'static, UsbBus>,
         mut rows: Vec<Pin<Input<PullUp>>, ROWS>,
         mut cols: Vec<Pin<Output>, COLS>,
         mut leds: Vec<Pin<Output>, LEDS>,
+        eeprom: Eeprom,
     ) -> Self {
         let mut row_pins: Vec<EitherPin, ROWS> = Vec::new();
         let mut col_pins: Vec<EitherPin, COLS> = Vec::new();
@@ -99,143 +187,51 @@ impl Keyboard {
             led_pins.insert(i, EitherPin::Output(pin));
         }
         Self {
-            usb_device,
-            hid_class,
-            scan_type: ScanType::COL2ROW,
-            rows: row_pins,
-            cols: col_pins,
-            leds: led_pins,
-            state: State::new(),
-            last_button_state: [Released; BUTTONS],
+            hardware: Hardware {
+                rows: row_pins,
+                cols: col_pins,
+                leds: led_pins,
+                eeprom,
+            },
+            controller: Controller::new(ScanType::COL2ROW),
         }
     }
-    pub fn poll(&mut self) {
-        if self.usb_device.poll(&mut [&mut self.hid_class]) {
-            let mut report_buf = [0u8; 1];
-            if self.hid_class.pull_raw_output(&mut report_buf).is_ok() {
-                // Bit | Led
-                // 0   | Num lock
-                // 1   | Caps lock
-                // 2   | Scroll lock
-                // 3   | Composition Mode
-                // 4   | Kana Mode
-                if report_buf[0] & 2 != 0 {
-                    Self::low(self.leds.get_mut(1).unwrap());
-                } else {
-                    Self::high(self.leds.get_mut(1).unwrap());
-                }
-            }
-        }
-        if self.usb_device.state() == UsbDeviceState::Configured {
-            let scan = self.scan();
-            let button_state: [ButtonState; BUTTONS] = self.state.tick(&scan);
 
-            if button_state != self.last_button_state {
-                self.last_button_state = button_state;
-                let events = self.state.keys();
-                self.apply_functions(&events);
-                let kr: KeyboardReport = self.create_report(&events);
-                self.hid_class.push_input(&kr);
-                let led_state = self.state.led_state();
-            }
-            self.set_leds();
-            delay_ms(DELAY_MS);
+    /// Scan the matrix and queue a report if anything changed.
+    /// Called from the main loop whenever the scheduler says a scan is due.
+    pub fn scan(&mut self) {
+        let (configured, host_leds) = interrupt::free(|cs| {
+            (CONFIGURED.borrow(cs).get(), HOST_LEDS.borrow(cs).get())
+        });
+        // Bit | Led
+        // 0   | Num lock
+        // 1   | Caps lock
+        // 2   | Scroll lock
+        // 3   | Composition Mode
+        // 4   | Kana Mode
+        self.hardware.set_led(1, host_leds & 2 == 0);
+        if configured {
+            self.controller.poll(&mut self.hardware);
         }
     }
+}
 
-    fn scan(&mut self) -> Scan {
-        return match self.scan_type {
-            ScanType::ROW2COL => {
-                self.scan_row2col()
-            }
-            ScanType::COL2ROW => {
-                self.scan_col2row()
-            }
-        };
-    }
-
-    fn scan_row2col(&mut self) -> Scan {
-        let mut scan_state = Scan::new();
-        for (r, row) in self.rows.iter_mut().enumerate() {
-            Self::low(row);
-            for (c, col) in self.cols.iter_mut().enumerate() {
-                if Self::is_low(col) {
-                    scan_state.set_pressed(&r, &c);
-                }
-            }
-            Self::high(row);
-        }
-        scan_state
-    }
-    fn scan_col2row(&mut self) -> Scan {
-        Scan::new()
-    }
-
-    fn set_leds(&mut self) {
-        for (i, active) in self.state.led_state().iter().enumerate() {
-            match *active {
-                true => Self::high(self.leds.get_mut(i).unwrap()),
-                false => Self::low(self.leds.get_mut(i).unwrap()),
-            }
-        }
-    }
-
-    fn create_report(&mut self, events: &Vec<Key, BUTTONS>) -> KeyboardReport {
-        if !events.is_empty() {
-            events.iter()
-                .map(|key| match key {
-                    Key::Function(f) => f(&mut self.state),
-                    _ => {}
-                });
-
-            let mods: u8 = events.iter()
-                .map(|key| match key {
-                    Key::KeyCode(kc) => Some(*kc),
-                    _ => None,
-                })
-                .filter(Option::is_some)
-                .map(Option::unwrap)
-                .filter(k::is_mod)
-                .map(k::to_mod_bitfield)
-                .sum();
-
-            let mut key_codes = [0; 6];
-            for (i, k) in events.iter()
-                .map(|e| match e {
-                    Key::KeyCode(kc) => Some(*kc),
-                    _ => None,
-                })
-                .filter(Option::is_some)
-                .map(Option::unwrap)
-                .filter(k::is_not_mod)
-                .enumerate() {
-                if i > 5 { break; }
-                key_codes[i] = k;
-            }
-
-            return KeyboardReport {
-                modifier: mods,
-                reserved: 0,
-                leds: 0,
-                keycodes: key_codes,
-            };
-        }
-        KeyboardReport {
-            modifier: 0,
-            reserved: 0,
-            leds: 0,
-            keycodes: [0; 6],
+impl Hardware {
+    fn pin(&mut self, pin: MatrixPin) -> &mut EitherPin {
+        match pin {
+            MatrixPin::Row(r) => self.rows.get_mut(r).unwrap(),
+            MatrixPin::Col(c) => self.cols.get_mut(c).unwrap(),
         }
     }
 
-    fn is_low(pin: &mut EitherPin) -> bool {
+    fn pin_is_low(pin: &mut EitherPin) -> bool {
         match pin {
             EitherPin::Input(p) => { p.is_low() }
             EitherPin::Output(_) => { panic!() }
             EitherPin::None => { panic!() }
         }
     }
-    fn is_high(pin: &mut EitherPin) -> bool {
+    fn pin_is_high(pin: &mut EitherPin) -> bool {
         match pin {
             EitherPin::Input(p) => { p.is_high() }
             EitherPin::Output(_) => { panic!() }
@@ -258,12 +254,51 @@ impl Keyboard {
             EitherPin::None => {}
         }
     }
+}
+
+impl KeyboardHal for Hardware {
+    fn set_low(&mut self, pin: MatrixPin) {
+        Self::low(self.pin(pin));
+    }
+
+    fn set_high(&mut self, pin: MatrixPin) {
+        Self::high(self.pin(pin));
+    }
+
+    fn is_low(&mut self, pin: MatrixPin) -> bool {
+        Self::pin_is_low(self.pin(pin))
+    }
+
+    fn set_led(&mut self, led: usize, on: bool) {
+        match on {
+            true => Self::high(self.leds.get_mut(led).unwrap()),
+            false => Self::low(self.leds.get_mut(led).unwrap()),
+        }
+    }
+
+    fn millis(&self) -> u32 {
+        millis::millis()
+    }
+
+    fn protocol(&self) -> Protocol {
+        interrupt::free(|cs| PROTOCOL.borrow(cs).get())
+    }
+
+    fn idle_ms(&self) -> u16 {
+        interrupt::free(|cs| IDLE_MS.borrow(cs).get())
+    }
+
+    fn load_default_layer(&mut self) -> u8 {
+        self.eeprom.read_byte(DEFAULT_LAYER_ADDRESS)
+    }
+
+    fn save_default_layer(&mut self, layer: u8) {
+        self.eeprom.write_byte(DEFAULT_LAYER_ADDRESS, layer);
+    }
 
-    fn apply_functions(&mut self, keys: &Vec<Key, BUTTONS>) {
-        keys.iter()
-            .for_each(|k| match k {
-                Key::Function(f) => f(&mut self.state),
-                _ => {}
-            });
+    fn push_report(&mut self, report: &Report) -> bool {
+        // Queued reports are never dropped for a new one, a consumer, system or keyboard release
+        // or mouse movement would be lost with them. The controller pushes it again later.
+        interrupt::free(|cs| REPORTS.borrow(cs).borrow_mut().push_back(*report).is_ok())
     }
 }
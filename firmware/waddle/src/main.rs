@@ -28,22 +28,20 @@ use usb_device::{
 };
 use usbd_hid::{
     descriptor::{KeyboardReport, SerializedDescriptor},
-    hid_class::HIDClass,
+    hid_class::{HIDClass, HidClassSettings, HidCountryCode, HidProtocol, HidSubClass, ProtocolModeConfig},
 };
 use usbd_serial::SerialPort;
 
-use layout::{COLS, Layout, LEDS, ROWS};
+use waddle_core::layout::{COLS, Layout, LAYOUT, LEDS, ROWS};
+use waddle_core::report::REPORT_DESCRIPTOR;
+use waddle_core::vec;
 
-use crate::keyboard::{Keyboard, ScanType};
-use crate::layout::LAYOUT;
+use crate::keyboard::{Keyboard, Matrix};
 
-mod layout;
-mod state;
-mod keycode;
+mod idle;
 mod keyboard;
-mod position;
-mod macros;
-mod scan;
+mod millis;
+mod scheduler;
 
 /// Wrapper around a usb-cdc SerialPort
 /// to be able to use the `write!()` macro with it
@@ -63,6 +61,7 @@ fn main() -> ! {
     let pins: Pins = pins!(peripherals);
     let pll = peripherals.PLL;
     let usb = peripherals.USB_DEVICE;
+    millis::init(peripherals.TC0);
 
     // Configure pll
     // Set to 8MHz
@@ -74,7 +73,7 @@ fn main() -> ! {
     // Wait until the bit is set
     while pll.pllcsr.read().plock().bit_is_clear() {}
 
-    unsafe {
+    let mut matrix = unsafe {
         let usb_bus = unsafe {
             static mut UB: Option<UsbBusAllocator<UsbBus>> = None;
             &*UB.insert(UsbBus::new(usb))
@@ -84,14 +83,20 @@ fn main() -> ! {
         // Set up the USB Communications Class Device driver for debugging
         let mut debug_port = DebugPort(SerialPort::new(USB_BUS.unwrap()));
 
-        init_keyboard(pins);
+        let matrix = init_keyboard(pins, arduino_hal::Eeprom::new(peripherals.EEPROM));
 
         write!(debug_port, "hello").unwrap();
-        interrupt::enable()
+        interrupt::enable();
+        matrix
     };
 
 
     loop {
+        if scheduler::scan_due() {
+            matrix.scan();
+            // The USB interrupts drain the queue too, but only fire on bus activity.
+            interrupt::free(|_| unsafe { KEYBOARD.as_mut().unwrap().flush() });
+        }
         sleep();
     }
 }
@@ -99,9 +104,16 @@ fn main() -> ! {
 static mut USB_BUS: Option<&UsbBusAllocator<UsbBus>> = None;
 static mut KEYBOARD: Option<Keyboard> = None;
 
-fn init_keyboard(pins: Pins) {
+fn init_keyboard(pins: Pins, eeprom: arduino_hal::Eeprom) -> Matrix {
     unsafe {
-        let hid_class = HIDClass::new(USB_BUS.unwrap(), KeyboardReport::desc(), 1);
+        let boot_class = HIDClass::new_with_settings(USB_BUS.unwrap(), KeyboardReport::desc(), 1, HidClassSettings {
+            subclass: HidSubClass::Boot,
+            protocol: HidProtocol::Keyboard,
+            config: ProtocolModeConfig::DefaultBehavior,
+            locale: HidCountryCode::NotSupported,
+        });
+        // IN only, the atmega32u4 does not have endpoints to spare
+        let report_class = HIDClass::new_ep_in(USB_BUS.unwrap(), REPORT_DESCRIPTOR, 1);
         let usb_device = UsbDeviceBuilder::new(USB_BUS.unwrap(), UsbVidPid(0x16c0, 0x27db))
             .manufacturer("qwelyt")
             .product("waddle")
@@ -136,13 +148,17 @@ fn init_keyboard(pins: Pins) {
         ];
         leds.iter_mut().map(|p| p.set_high());
 
-        KEYBOARD = Some(Keyboard::row2col(
+        KEYBOARD = Some(Keyboard::new(
             usb_device,
-            hid_class,
+            boot_class,
+            report_class,
+        ));
+        Matrix::row2col(
             rows,
             cols,
             leds,
-        ))
+            eeprom,
+        )
     }
 }
 
@@ -0,0 +1,40 @@
+//! Monotonic millisecond clock driven by Timer0.
+//!
+//! Timer0 runs in CTC mode and fires `TIMER0_COMPA` once every ms, which bumps a counter.
+//! Unlike `delay_ms` this keeps time no matter how often, or from where, the matrix is scanned.
+use core::cell::Cell;
+
+use arduino_hal::pac::TC0;
+use avr_device::interrupt;
+use avr_device::interrupt::Mutex;
+
+use crate::scheduler;
+
+const CPU_HZ: u32 = 16_000_000;
+const PRESCALER: u32 = 64;
+const TIMER_COUNTS: u32 = CPU_HZ / PRESCALER / 1000;
+
+static MILLIS_COUNTER: Mutex<Cell<u32>> = Mutex::new(Cell::new(0));
+
+pub fn init(tc0: TC0) {
+    tc0.tccr0a.write(|w| w.wgm0().ctc());
+    tc0.ocr0a.write(|w| w.bits((TIMER_COUNTS - 1) as u8));
+    tc0.tccr0b.write(|w| w.cs0().prescale_64());
+    tc0.timsk0.write(|w| w.ocie0a().set_bit());
+
+    interrupt::free(|cs| MILLIS_COUNTER.borrow(cs).set(0));
+}
+
+pub fn millis() -> u32 {
+    interrupt::free(|cs| MILLIS_COUNTER.borrow(cs).get())
+}
+
+#[interrupt(atmega32u4)]
+fn TIMER0_COMPA() {
+    interrupt::free(|cs| {
+        let counter = MILLIS_COUNTER.borrow(cs);
+        let now = counter.get().wrapping_add(1);
+        counter.set(now);
+        scheduler::tick(cs, now);
+    })
+}
@@ -0,0 +1,26 @@
+//! Decides when the main loop should scan the matrix.
+//!
+//! The Timer0 millisecond interrupt calls [`tick`], which raises a flag every
+//! [`SCAN_INTERVAL_MS`]. The main loop takes the flag and does the actual scanning, so no
+//! interrupt ever has to wait for the matrix.
+use core::cell::Cell;
+
+use avr_device::interrupt;
+use avr_device::interrupt::{CriticalSection, Mutex};
+
+/// How often to scan the matrix. 1 ms gives a 1 kHz scan rate.
+pub const SCAN_INTERVAL_MS: u32 = 1;
+
+static SCAN_DUE: Mutex<Cell<bool>> = Mutex::new(Cell::new(false));
+
+/// Called from the timer interrupt with the current time.
+pub fn tick(cs: CriticalSection, now: u32) {
+    if now % SCAN_INTERVAL_MS == 0 {
+        SCAN_DUE.borrow(cs).set(true);
+    }
+}
+
+/// Whether a scan is due. Clears the flag, so each tick gives one scan.
+pub fn scan_due() -> bool {
+    interrupt::free(|cs| SCAN_DUE.borrow(cs).replace(false))
+}